@@ -0,0 +1,87 @@
+use ::base64::{
+	engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD},
+	Engine as _,
+};
+
+use crate::Error;
+
+/// Base64 alphabet and padding combinations used by streaming sites.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Base64 {
+	Standard,
+	StandardNoPad,
+	UrlSafe,
+	UrlSafeNoPad,
+}
+
+impl Base64 {
+	pub fn encode(self, input: &[u8]) -> Vec<u8> {
+		match self {
+			Base64::Standard => STANDARD.encode(input),
+			Base64::StandardNoPad => STANDARD_NO_PAD.encode(input),
+			Base64::UrlSafe => URL_SAFE.encode(input),
+			Base64::UrlSafeNoPad => URL_SAFE_NO_PAD.encode(input),
+		}
+		.into_bytes()
+	}
+
+	pub fn decode(self, input: &[u8]) -> Result<Vec<u8>, Error> {
+		let decoded = match self {
+			Base64::Standard => STANDARD.decode(input),
+			Base64::StandardNoPad => STANDARD_NO_PAD.decode(input),
+			Base64::UrlSafe => URL_SAFE.decode(input),
+			Base64::UrlSafeNoPad => URL_SAFE_NO_PAD.decode(input),
+		}?;
+		Ok(decoded)
+	}
+}
+
+pub fn url_safe_base64(input: &[u8]) -> Vec<u8> {
+	Base64::UrlSafeNoPad.encode(input)
+}
+
+pub fn decode_url_safe_base64(input: &[u8]) -> Result<Vec<u8>, Error> {
+	Base64::UrlSafeNoPad.decode(input)
+}
+
+#[cfg(test)]
+mod tests {
+	use proptest::prelude::*;
+
+	use super::*;
+
+	#[test]
+	fn test_url_safe_base64() {
+		let test = url_safe_base64(b"Hello, World!");
+		assert_eq!(test, b"SGVsbG8sIFdvcmxkIQ");
+	}
+
+	#[test]
+	fn test_decode_url_safe_base64() {
+		let test = decode_url_safe_base64(b"SGVsbG8sIFdvcmxkIQ").unwrap();
+		assert_eq!(test, b"Hello, World!");
+		assert!(matches!(
+			decode_url_safe_base64(b"not base64!"),
+			Err(Error::Base64(_))
+		));
+	}
+
+	#[test]
+	fn test_variants() {
+		let data = b"\xfb\xff?";
+		assert_eq!(Base64::Standard.encode(data), b"+/8/");
+		assert_eq!(Base64::UrlSafe.encode(data), b"-_8_");
+		assert_eq!(Base64::Standard.encode(b"a"), b"YQ==");
+		assert_eq!(Base64::StandardNoPad.encode(b"a"), b"YQ");
+		assert!(Base64::Standard.decode(b"YQ").is_err());
+	}
+
+	proptest! {
+		#[test]
+		fn test_base64_round_trip(data: Vec<u8>) {
+			for variant in [Base64::Standard, Base64::StandardNoPad, Base64::UrlSafe, Base64::UrlSafeNoPad] {
+				prop_assert_eq!(variant.decode(&variant.encode(&data)).unwrap(), data.clone());
+			}
+		}
+	}
+}
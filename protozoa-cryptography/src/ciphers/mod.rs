@@ -1 +1,32 @@
-pub mod rc4;
\ No newline at end of file
+//! Byte-level building blocks behind the AnimeKai and MegaUp schemes, usable for other providers.
+
+pub mod base64;
+pub mod rc4;
+pub mod substitution;
+
+pub use self::{base64::Base64, rc4::Rc4, substitution::Substitution};
+
+/// Reverses `input` byte by byte.
+pub fn reverse(input: &[u8]) -> Vec<u8> {
+	input.iter().rev().copied().collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use proptest::prelude::*;
+
+	use super::*;
+
+	#[test]
+	fn test_reverse() {
+		let test = reverse(b"Hello, World!");
+		assert_eq!(test, b"!dlroW ,olleH");
+	}
+
+	proptest! {
+		#[test]
+		fn test_reverse_round_trip(data: Vec<u8>) {
+			prop_assert_eq!(reverse(&reverse(&data)), data);
+		}
+	}
+}
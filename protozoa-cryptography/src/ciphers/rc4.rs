@@ -1,99 +1,92 @@
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use crate::Error;
+
+/// RC4 keyed once; `process` starts a fresh keystream each call while `apply` continues the current one.
+#[derive(Clone)]
+pub struct Rc4 {
+	s: [u8; 256],
+	i: u8,
+	j: u8,
+}
 
-pub fn rc4(key: &str, data: &str) -> String {
-	let mut s: Vec<u8> = (0..=255).collect();
-	let mut j: usize = 0;
+impl Rc4 {
+	pub fn new(key: &[u8]) -> Result<Self, Error> {
+		if key.is_empty() {
+			return Err(Error::Rc4);
+		}
 
-	let key_utf16: Vec<u16> = key.encode_utf16().collect();
+		let mut s: [u8; 256] = std::array::from_fn(|i| i as u8);
+		let mut j: u8 = 0;
+		for i in 0..256 {
+			j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+			s.swap(i, j as usize);
+		}
 
-	for i in 0..256 {
-		j = (j + s[i] as usize + key_utf16[i % key_utf16.len()] as usize) % 256;
-		s.swap(i, j);
+		Ok(Rc4 { s, i: 0, j: 0 })
 	}
 
-	let mut i: usize = 0;
-	j = 0;
-	let mut res = Vec::with_capacity(data.len() * 2);
-	let data_utf16: Vec<u16> = data.encode_utf16().collect();
-
-	for &byte in data_utf16.iter() {
-		i = (i + 1) % 256;
-		j = (j + s[i] as usize) % 256;
-		s.swap(i, j);
-		let k = s[(s[i] as usize + s[j] as usize) % 256];
-		res.push((byte ^ k as u16) as u8);
+	/// XORs `data` in place with the next bytes of the keystream.
+	pub fn apply(&mut self, data: &mut [u8]) {
+		for byte in data {
+			self.i = self.i.wrapping_add(1);
+			self.j = self.j.wrapping_add(self.s[self.i as usize]);
+			self.s.swap(self.i as usize, self.j as usize);
+			let k = self.s[self.s[self.i as usize].wrapping_add(self.s[self.j as usize]) as usize];
+			*byte ^= k;
+		}
 	}
 
-	String::from_utf16_lossy(&res.iter().map(|&b| b as u16).collect::<Vec<_>>())
-}
-
-pub fn reverse(input: &str) -> String {
-	input.chars().rev().collect()
-}
-
-pub fn url_safe_base64(input: &str) -> String {
-	let mut byte_vec: Vec<u8> = Vec::new();
-	for char in input.chars() {
-		let byte = char as u8;
-		byte_vec.push(byte);
+	/// Encrypts or decrypts `data` from the start of the keystream, leaving `self` untouched.
+	pub fn process(&self, data: &[u8]) -> Vec<u8> {
+		let mut output = data.to_vec();
+		self.clone().apply(&mut output);
+		output
 	}
-	URL_SAFE_NO_PAD.encode(byte_vec)
 }
 
-pub fn decode_url_safe_base64(input: &str) -> String {
-	let mut byte_vec = URL_SAFE_NO_PAD.decode(input).unwrap();
-	let mut char_vec: Vec<char> = Vec::new();
-	for byte in byte_vec.drain(..) {
-		char_vec.push(byte as char);
+impl std::fmt::Debug for Rc4 {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Rc4").finish_non_exhaustive()
 	}
-	char_vec.into_iter().collect()
 }
 
-pub fn replace(input: &str, search_chars: &str, replace_chars: &str) -> String {
-	let mut replacement_map = std::collections::HashMap::new();
-
-	for (s, r) in search_chars.chars().zip(replace_chars.chars()) {
-		replacement_map.insert(s, r);
-	}
-
-	input
-		.chars()
-		.map(|c| replacement_map.get(&c).copied().unwrap_or(c))
-		.collect()
+/// One-shot RC4 over `data`.
+pub fn rc4(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+	Ok(Rc4::new(key)?.process(data))
 }
 
 #[cfg(test)]
 mod tests {
+	use proptest::prelude::*;
+
 	use super::*;
 
 	#[test]
 	fn test_rc4() {
-		let test = rc4("key", "Plaintext");
-		println!("{}", test);
-		assert_eq!(test, "[\0U\u{84}Jû\u{1e}2<");
-	}
-
-	#[test]
-	fn test_reverse() {
-		let test = reverse("Hello, World!");
-		assert_eq!(test, "!dlroW ,olleH");
+		let test = rc4(b"key", b"Plaintext").unwrap();
+		assert_eq!(test, [0x5b, 0x00, 0x55, 0x84, 0x4a, 0xfb, 0x1e, 0x32, 0x3c]);
+		assert!(matches!(rc4(b"", b"Plaintext"), Err(Error::Rc4)));
 	}
 
 	#[test]
-	fn test_url_safe_base64() {
-		let test = url_safe_base64("Hello, World!");
-		assert_eq!(test, "SGVsbG8sIFdvcmxkIQ");
+	fn test_rc4_streaming() {
+		let cipher = Rc4::new(b"Secret").unwrap();
+		let mut stream = cipher.clone();
+		let mut data = *b"Attack at dawn";
+		let (head, tail) = data.split_at_mut(5);
+		stream.apply(head);
+		stream.apply(tail);
+		assert_eq!(data.to_vec(), cipher.process(b"Attack at dawn"));
+		assert_eq!(
+			cipher.process(b"Attack at dawn"),
+			[0x45, 0xa0, 0x1f, 0x64, 0x5f, 0xc3, 0x5b, 0x38, 0x35, 0x52, 0x54, 0x4b, 0x9b, 0xf5]
+		);
 	}
 
-	#[test]
-	fn test_decode_url_safe_base64() {
-		let test = decode_url_safe_base64("SGVsbG8sIFdvcmxkIQ");
-		assert_eq!(test, "Hello, World!");
-	}
-
-	#[test]
-	fn test_replace() {
-		let test = replace("Hello, World!", "HW", "hw");
-		assert_eq!(test, "hello, world!");
+	proptest! {
+		#[test]
+		fn test_rc4_round_trip(key in prop::collection::vec(any::<u8>(), 1..64), data: Vec<u8>) {
+			let cipher = Rc4::new(&key).unwrap();
+			prop_assert_eq!(cipher.process(&cipher.process(&data)), data);
+		}
 	}
 }
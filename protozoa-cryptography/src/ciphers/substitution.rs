@@ -0,0 +1,84 @@
+use crate::Error;
+
+/// Byte-for-byte substitution table, mapping each byte of `from` to the byte at the same position in `to`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Substitution {
+	table: [u8; 256],
+}
+
+impl Substitution {
+	pub fn new(from: &[u8], to: &[u8]) -> Result<Self, Error> {
+		if from.len() != to.len() {
+			return Err(Error::Substitution {
+				search: from.len(),
+				replace: to.len(),
+			});
+		}
+
+		let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+		for (&f, &t) in from.iter().zip(to) {
+			table[f as usize] = t;
+		}
+
+		Ok(Substitution { table })
+	}
+
+	pub fn apply(&self, input: &[u8]) -> Vec<u8> {
+		input
+			.iter()
+			.map(|&byte| self.table[byte as usize])
+			.collect()
+	}
+
+	/// The table undoing `apply`, exact as long as `to` is a permutation of `from`.
+	pub fn inverse(&self) -> Self {
+		let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+		for (byte, &mapped) in self.table.iter().enumerate() {
+			if mapped as usize != byte {
+				table[mapped as usize] = byte as u8;
+			}
+		}
+
+		Substitution { table }
+	}
+}
+
+/// One-shot substitution of `search` with `replace` over `input`.
+pub fn replace(input: &[u8], search: &[u8], replace: &[u8]) -> Result<Vec<u8>, Error> {
+	Ok(Substitution::new(search, replace)?.apply(input))
+}
+
+#[cfg(test)]
+mod tests {
+	use proptest::prelude::*;
+
+	use super::*;
+
+	#[test]
+	fn test_replace() {
+		let test = replace(b"Hello, World!", b"HW", b"hw").unwrap();
+		assert_eq!(test, b"hello, world!");
+		assert!(matches!(
+			replace(b"Hello", b"HW", b"h"),
+			Err(Error::Substitution { .. })
+		));
+	}
+
+	#[test]
+	fn test_inverse() {
+		let substitution = Substitution::new(b"1wctXeHqb2", b"1tecHq2Xbw").unwrap();
+		assert_eq!(
+			substitution.inverse(),
+			Substitution::new(b"1tecHq2Xbw", b"1wctXeHqb2").unwrap()
+		);
+	}
+
+	proptest! {
+		#[test]
+		fn test_substitution_round_trip(from in Just((0..=255u8).collect::<Vec<_>>()).prop_shuffle(), data: Vec<u8>) {
+			let to: Vec<u8> = (0..=255).collect();
+			let substitution = Substitution::new(&from, &to).unwrap();
+			prop_assert_eq!(substitution.inverse().apply(&substitution.apply(&data)), data);
+		}
+	}
+}
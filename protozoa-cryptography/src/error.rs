@@ -0,0 +1,44 @@
+use std::string::FromUtf8Error;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+	#[cfg(any(feature = "animekai", feature = "openssl"))]
+	#[error("base64 decoding failed: {0}")]
+	Base64(#[from] base64::DecodeError),
+	#[error("rc4 key must not be empty")]
+	Rc4,
+	#[error("substitution alphabets differ in length ({search} and {replace})")]
+	Substitution { search: usize, replace: usize },
+	#[error("substitution table isn't a permutation, so it can't be inverted")]
+	NotBijective,
+	#[error("url decoding failed: {0}")]
+	UrlDecode(FromUtf8Error),
+	#[error("ciphertext is too short ({0} bytes)")]
+	Truncated(usize),
+	#[error("aes padding is invalid")]
+	AesPadding,
+	#[error("payload has no Salted__ header")]
+	NotSalted,
+	#[error("unsupported aes key length {0}")]
+	KeyLength(usize),
+	#[error("iv must be 16 bytes, got {0}")]
+	IvLength(usize),
+	#[error("decrypted text is not valid utf-8: {0}")]
+	Utf8(FromUtf8Error),
+	#[error("{scheme} keys are outdated")]
+	KeysOutdated {
+		scheme: &'static str,
+		#[source]
+		source: Option<Box<Error>>,
+	},
+	#[error("invalid key schedule: {0}")]
+	Json(#[from] serde_json::Error),
+	#[cfg(feature = "animekai")]
+	#[error("invalid key schedule: {0}")]
+	Toml(#[from] toml::de::Error),
+	#[error("failed to read key schedule: {0}")]
+	Io(#[from] std::io::Error),
+	#[cfg(feature = "remote-keys")]
+	#[error("failed to fetch key schedule: {0}")]
+	Fetch(#[from] reqwest::Error),
+}
@@ -1,2 +1,13 @@
-mod ciphers;
+#[cfg(feature = "animekai")]
+pub mod ciphers;
+mod error;
+#[cfg(feature = "openssl")]
+pub mod openssl;
+#[cfg(feature = "animekai")]
+pub mod pipeline;
 pub mod sources;
+
+pub use error::Error;
+
+// Raw bodies are logged at trace level under the same target as protozoa's, so one filter dumps both.
+pub const BODY_TARGET: &str = "protozoa::body";
@@ -0,0 +1,239 @@
+//! OpenSSL `enc` / CryptoJS compatible `Salted__` payloads: EVP_BytesToKey key derivation with AES-CBC.
+
+use aes::{Aes128, Aes192, Aes256};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use cbc::{
+	cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit},
+	Decryptor, Encryptor,
+};
+use sha1::Sha1;
+use sha2::{Digest as _, Sha256};
+
+use crate::Error;
+
+const MAGIC: &[u8] = b"Salted__";
+const SALT_LEN: usize = 8;
+const BLOCK_LEN: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Digest {
+	#[default]
+	Md5,
+	Sha1,
+	Sha256,
+}
+
+impl Digest {
+	fn hash(self, data: &[u8]) -> Vec<u8> {
+		match self {
+			Digest::Md5 => md5::compute(data).to_vec(),
+			Digest::Sha1 => Sha1::digest(data).to_vec(),
+			Digest::Sha256 => Sha256::digest(data).to_vec(),
+		}
+	}
+}
+
+/// Key derivation and cipher parameters; the default matches CryptoJS (MD5, AES-256-CBC).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+	pub digest: Digest,
+	/// 16, 24 or 32 bytes, selecting AES-128, AES-192 or AES-256.
+	pub key_len: usize,
+	pub iv_len: usize,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Config {
+			digest: Digest::Md5,
+			key_len: 32,
+			iv_len: BLOCK_LEN,
+		}
+	}
+}
+
+impl Config {
+	/// EVP_BytesToKey with a single iteration, returning `(key, iv)`.
+	pub fn derive(&self, password: &[u8], salt: &[u8]) -> (Vec<u8>, Vec<u8>) {
+		let mut derived = Vec::with_capacity(self.key_len + self.iv_len);
+		let mut block = Vec::new();
+
+		while derived.len() < self.key_len + self.iv_len {
+			block = self.digest.hash(&[&block, password, salt].concat());
+			derived.extend_from_slice(&block);
+		}
+
+		let iv = derived[self.key_len..self.key_len + self.iv_len].to_vec();
+		derived.truncate(self.key_len);
+		(derived, iv)
+	}
+
+	/// Encrypts into `Salted__ || salt || ciphertext`, as `openssl enc` writes it.
+	pub fn encrypt(
+		&self, plaintext: &[u8], password: &[u8], salt: &[u8; SALT_LEN],
+	) -> Result<Vec<u8>, Error> {
+		let (key, iv) = self.derive(password, salt);
+		let ciphertext = aes_cbc_encrypt(plaintext, &key, &iv)?;
+		Ok([MAGIC, salt, &ciphertext].concat())
+	}
+
+	pub fn decrypt(&self, payload: &[u8], password: &[u8]) -> Result<Vec<u8>, Error> {
+		let header = MAGIC.len() + SALT_LEN;
+		if payload.len() < header {
+			return Err(Error::Truncated(payload.len()));
+		}
+		if !payload.starts_with(MAGIC) {
+			return Err(Error::NotSalted);
+		}
+
+		let (key, iv) = self.derive(password, &payload[MAGIC.len()..header]);
+		aes_cbc_decrypt(&payload[header..], &key, &iv)
+	}
+
+	pub fn encrypt_base64(
+		&self, plaintext: &[u8], password: &[u8], salt: &[u8; SALT_LEN],
+	) -> Result<String, Error> {
+		Ok(STANDARD.encode(self.encrypt(plaintext, password, salt)?))
+	}
+
+	pub fn decrypt_base64(&self, payload: &str, password: &[u8]) -> Result<Vec<u8>, Error> {
+		self.decrypt(&STANDARD.decode(payload.trim())?, password)
+	}
+}
+
+pub fn aes_cbc_encrypt(plaintext: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>, Error> {
+	if iv.len() != BLOCK_LEN {
+		return Err(Error::IvLength(iv.len()));
+	}
+
+	let ciphertext = match key.len() {
+		16 => Encryptor::<Aes128>::new(key.into(), iv.into())
+			.encrypt_padded_vec_mut::<Pkcs7>(plaintext),
+		24 => Encryptor::<Aes192>::new(key.into(), iv.into())
+			.encrypt_padded_vec_mut::<Pkcs7>(plaintext),
+		32 => Encryptor::<Aes256>::new(key.into(), iv.into())
+			.encrypt_padded_vec_mut::<Pkcs7>(plaintext),
+		len => return Err(Error::KeyLength(len)),
+	};
+
+	Ok(ciphertext)
+}
+
+pub fn aes_cbc_decrypt(ciphertext: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>, Error> {
+	if iv.len() != BLOCK_LEN {
+		return Err(Error::IvLength(iv.len()));
+	}
+
+	let plaintext = match key.len() {
+		16 => Decryptor::<Aes128>::new(key.into(), iv.into())
+			.decrypt_padded_vec_mut::<Pkcs7>(ciphertext),
+		24 => Decryptor::<Aes192>::new(key.into(), iv.into())
+			.decrypt_padded_vec_mut::<Pkcs7>(ciphertext),
+		32 => Decryptor::<Aes256>::new(key.into(), iv.into())
+			.decrypt_padded_vec_mut::<Pkcs7>(ciphertext),
+		len => return Err(Error::KeyLength(len)),
+	};
+
+	plaintext.map_err(|_| Error::AesPadding)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SALT: &[u8; SALT_LEN] = &[1, 2, 3, 4, 5, 6, 7, 8];
+	const SOURCES: &[u8] = br#"{"sources":[{"file":"https://example.com/master.m3u8"}]}"#;
+
+	fn hex(s: &str) -> Vec<u8> {
+		(0..s.len())
+			.step_by(2)
+			.map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+			.collect()
+	}
+
+	// `openssl enc -aes-256-cbc -md md5 -S 0102030405060708 -pass pass:secret -P`
+	#[test]
+	fn test_derive() {
+		let (key, iv) = Config::default().derive(b"secret", SALT);
+		assert_eq!(
+			key,
+			hex("C9E5A1BD216DBE1317E230CEF48F38EE7F0E17AD64022144BCCEC4A1AA2879AB")
+		);
+		assert_eq!(iv, hex("E24B32BBBC4EF02ECBCB6576523AD893"));
+
+		let config = Config {
+			digest: Digest::Sha256,
+			key_len: 16,
+			iv_len: 16,
+		};
+		let (key, iv) = config.derive(b"secret", SALT);
+		assert_eq!(key, hex("03B375940CB96C16F84FAA87F5EF39CC"));
+		assert_eq!(iv, hex("0BC7066CCD3E14456D9D74E438E35832"));
+	}
+
+	// `openssl enc -<cipher> -md <digest> -S 0102030405060708 -pass pass:secret -base64 -A`, which omits the header.
+	#[test]
+	fn test_encrypt() {
+		let vectors = [
+			(Digest::Md5, 32, "eSkzjaxUc2OD5EI7vcekdttit8la4hBA+DON/VPIRa106RY0PL7EyViu9x6nDi27Iq1pkCMHRLCgoojPz5U72A=="),
+			(Digest::Sha256, 16, "K10ydwiICwvkqT6Iw1Y3zMsKEu69yI9aF2IdCz6aoY3BandQ21efc72dV+KB6hINz8VYG7iZOpVbO2cI3Wiepw=="),
+			(Digest::Sha1, 24, "MXBf3zYYf6/fCvdC+94yuXz/bXZT5+Dy6YG19iokfUoYFw9/foFTUznsRenkzN67yIZ8aklRJjSK7he42rkX2A=="),
+		];
+
+		for (digest, key_len, expected) in vectors {
+			let config = Config {
+				digest,
+				key_len,
+				iv_len: 16,
+			};
+			let payload = config.encrypt(SOURCES, b"secret", SALT).unwrap();
+			assert_eq!(&payload[..16], b"Salted__\x01\x02\x03\x04\x05\x06\x07\x08");
+			assert_eq!(STANDARD.encode(&payload[16..]), expected);
+			assert_eq!(config.decrypt(&payload, b"secret").unwrap(), SOURCES);
+		}
+	}
+
+	// `printf 'Attack at dawn' | openssl enc -<cipher> -md <digest> -pass pass:secret -base64 -A`
+	#[test]
+	fn test_decrypt() {
+		let decrypted = Config::default()
+			.decrypt_base64("U2FsdGVkX19v+64AkeOj+d+7nlerPIpKtrXibieEQS4=", b"secret")
+			.unwrap();
+		assert_eq!(decrypted, b"Attack at dawn");
+
+		let config = Config {
+			digest: Digest::Sha1,
+			key_len: 24,
+			iv_len: 16,
+		};
+		let decrypted = config
+			.decrypt_base64("U2FsdGVkX1+ZtVVzpVkBhCM5r1SpqrSlfw77N6m0dXU=", b"secret")
+			.unwrap();
+		assert_eq!(decrypted, b"Attack at dawn");
+	}
+
+	#[test]
+	fn test_errors() {
+		let config = Config::default();
+		assert!(matches!(
+			config.decrypt(b"Salted__", b"secret"),
+			Err(Error::Truncated(8))
+		));
+		assert!(matches!(
+			config.decrypt(b"Unsalted0123456789abcdef", b"secret"),
+			Err(Error::NotSalted)
+		));
+		assert!(matches!(
+			config.decrypt_base64("U2FsdGVkX19v+64AkeOj+d+7nlerPIpKtrXibieEQS4=", b"wrong"),
+			Err(Error::AesPadding)
+		));
+		assert!(matches!(
+			aes_cbc_encrypt(b"", &[0; 20], &[0; 16]),
+			Err(Error::KeyLength(20))
+		));
+		assert!(matches!(
+			aes_cbc_decrypt(b"", &[0; 32], &[0; 8]),
+			Err(Error::IvLength(8))
+		));
+	}
+}
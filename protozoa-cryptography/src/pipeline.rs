@@ -0,0 +1,387 @@
+use std::{
+	path::Path,
+	sync::{Arc, RwLock},
+};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	ciphers::{
+		base64::{decode_url_safe_base64, url_safe_base64},
+		rc4::Rc4,
+		reverse,
+		substitution::Substitution,
+	},
+	Error,
+};
+
+// Bundled schedules, used until `set_keys` replaces them.
+const DEFAULT_KEYS: &str = include_str!("../keys.json");
+
+lazy_static! {
+	static ref KEYS: RwLock<Arc<Keys>> = RwLock::new(Arc::new(Keys::default()));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Step {
+	Rc4 { key: String },
+	Base64,
+	Reverse,
+	Substitute { from: String, to: String },
+	UrlEncode,
+}
+
+impl Step {
+	fn build(&self) -> Result<Stage, Error> {
+		match self {
+			Step::Rc4 { key } => Ok(Stage::Rc4(Box::new(Rc4::new(key.as_bytes())?))),
+			Step::Base64 => Ok(Stage::Base64),
+			Step::Reverse => Ok(Stage::Reverse),
+			Step::Substitute { from, to } => {
+				let forward = Substitution::new(from.as_bytes(), to.as_bytes())?;
+				// Decryption applies the inverse table, which only undoes `to` if it reorders `from`.
+				if !is_permutation(from.as_bytes(), to.as_bytes()) {
+					return Err(Error::NotBijective);
+				}
+				Ok(Stage::Substitute {
+					inverse: Box::new(forward.inverse()),
+					forward: Box::new(forward),
+				})
+			}
+			Step::UrlEncode => Ok(Stage::UrlEncode),
+		}
+	}
+}
+
+// A step with its cipher keyed and its tables built, so decrypting doesn't redo that per call.
+#[derive(Debug, Clone)]
+enum Stage {
+	Rc4(Box<Rc4>),
+	Base64,
+	Reverse,
+	Substitute {
+		forward: Box<Substitution>,
+		inverse: Box<Substitution>,
+	},
+	UrlEncode,
+}
+
+impl Stage {
+	fn forward(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+		match self {
+			Stage::Rc4(cipher) => Ok(cipher.process(data)),
+			Stage::Base64 => Ok(url_safe_base64(data)),
+			Stage::Reverse => Ok(reverse(data)),
+			Stage::Substitute { forward, .. } => Ok(forward.apply(data)),
+			Stage::UrlEncode => Ok(urlencoding::encode_binary(data).into_owned().into_bytes()),
+		}
+	}
+
+	fn inverse(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+		match self {
+			Stage::Rc4(cipher) => Ok(cipher.process(data)),
+			Stage::Base64 => decode_url_safe_base64(data),
+			Stage::Reverse => Ok(reverse(data)),
+			Stage::Substitute { inverse, .. } => Ok(inverse.apply(data)),
+			Stage::UrlEncode => {
+				let text = urlencoding::decode_binary(data).into_owned();
+				let text = String::from_utf8(text).map_err(Error::UrlDecode)?;
+				Ok(text.into_bytes())
+			}
+		}
+	}
+}
+
+fn is_permutation(from: &[u8], to: &[u8]) -> bool {
+	let mut from = from.to_vec();
+	let mut to = to.to_vec();
+	from.sort_unstable();
+	to.sort_unstable();
+	from == to && from.windows(2).all(|pair| pair[0] != pair[1])
+}
+
+// Steps are listed in the encrypt direction; decryption runs their inverses back to front.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Step>", into = "Vec<Step>")]
+pub struct Pipeline {
+	steps: Vec<Step>,
+	// Built once from `steps`, or None if one of them is invalid, which `validate` reports.
+	stages: Option<Vec<Stage>>,
+}
+
+impl PartialEq for Pipeline {
+	fn eq(&self, other: &Self) -> bool {
+		self.steps == other.steps
+	}
+}
+
+impl Eq for Pipeline {}
+
+impl From<Vec<Step>> for Pipeline {
+	fn from(steps: Vec<Step>) -> Self {
+		Pipeline::new(steps)
+	}
+}
+
+impl From<Pipeline> for Vec<Step> {
+	fn from(pipeline: Pipeline) -> Self {
+		pipeline.steps
+	}
+}
+
+impl Pipeline {
+	pub fn new(steps: Vec<Step>) -> Self {
+		let stages = steps.iter().map(Step::build).collect::<Result<_, _>>().ok();
+		Pipeline { steps, stages }
+	}
+
+	pub fn steps(&self) -> &[Step] {
+		&self.steps
+	}
+
+	fn stages(&self) -> Result<&[Stage], Error> {
+		match &self.stages {
+			Some(stages) => Ok(stages),
+			None => Err(self
+				.validate()
+				.expect_err("stages are only missing for invalid steps")),
+		}
+	}
+
+	pub fn encrypt(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+		self.stages()?
+			.iter()
+			.try_fold(input.to_vec(), |data, stage| stage.forward(&data))
+	}
+
+	pub fn decrypt(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+		self.stages()?
+			.iter()
+			.rev()
+			.try_fold(input.to_vec(), |data, stage| stage.inverse(&data))
+	}
+
+	pub fn encrypt_str(&self, input: &str) -> Result<String, Error> {
+		String::from_utf8(self.encrypt(input.as_bytes())?).map_err(Error::Utf8)
+	}
+
+	pub fn decrypt_str(&self, input: &str) -> Result<String, Error> {
+		String::from_utf8(self.decrypt(input.as_bytes())?).map_err(Error::Utf8)
+	}
+
+	pub fn validate(&self) -> Result<(), Error> {
+		self.steps
+			.iter()
+			.try_for_each(|step| step.build().map(drop))
+	}
+
+	// Rotated keys rarely fail outright, so the output must also be a JSON object with the fields the site sends.
+	#[tracing::instrument(skip_all, fields(scheme, steps = self.steps.len(), bytes = input.len()))]
+	pub(crate) fn decrypt_json(
+		&self, scheme: &'static str, input: &str, fields: &[&str],
+	) -> Result<String, Error> {
+		let outdated = |stage: &str, source: Option<Error>| {
+			tracing::debug!(stage, error = ?source, "keys outdated");
+			Error::KeysOutdated {
+				scheme,
+				source: source.map(Box::new),
+			}
+		};
+
+		let text = self
+			.decrypt_str(input)
+			.map_err(|e| outdated("decrypt", Some(e)))?;
+		tracing::trace!(target: crate::BODY_TARGET, body = %text, "decrypted");
+		let value: serde_json::Value =
+			serde_json::from_str(&text).map_err(|e| outdated("parse", Some(e.into())))?;
+		if !fields.iter().all(|field| value.get(field).is_some()) {
+			return Err(outdated("fields", None));
+		}
+
+		Ok(text)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keys {
+	pub animekai: Pipeline,
+	pub megaup: Pipeline,
+}
+
+impl Default for Keys {
+	fn default() -> Self {
+		serde_json::from_str(DEFAULT_KEYS).expect("bundled keys.json is valid")
+	}
+}
+
+impl Keys {
+	pub fn from_json(source: &str) -> Result<Self, Error> {
+		let keys: Keys = serde_json::from_str(source)?;
+		keys.validate()?;
+		Ok(keys)
+	}
+
+	pub fn from_toml(source: &str) -> Result<Self, Error> {
+		let keys: Keys = toml::from_str(source)?;
+		keys.validate()?;
+		Ok(keys)
+	}
+
+	// Picks the format from the extension, anything other than `.toml` is read as JSON.
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+		let path = path.as_ref();
+		let source = std::fs::read_to_string(path)?;
+		match path.extension().and_then(|ext| ext.to_str()) {
+			Some("toml") => Keys::from_toml(&source),
+			_ => Keys::from_json(&source),
+		}
+	}
+
+	#[cfg(feature = "remote-keys")]
+	pub async fn fetch(url: &str) -> Result<Self, Error> {
+		let source = reqwest::get(url).await?.error_for_status()?.text().await?;
+		match source.trim_start().starts_with('{') {
+			true => Keys::from_json(&source),
+			false => Keys::from_toml(&source),
+		}
+	}
+
+	fn validate(&self) -> Result<(), Error> {
+		self.animekai.validate()?;
+		self.megaup.validate()
+	}
+}
+
+pub fn keys() -> Arc<Keys> {
+	KEYS.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+// Swaps the schedules used by `sources::animekai` and `sources::megaup` for every later call.
+pub fn set_keys(keys: Keys) {
+	*KEYS.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(keys);
+}
+
+pub fn reset_keys() {
+	set_keys(Keys::default());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_round_trip() {
+		let pipeline = Pipeline::new(vec![
+			Step::UrlEncode,
+			Step::Rc4 { key: "key".into() },
+			Step::Base64,
+			Step::Reverse,
+			Step::Substitute {
+				from: "abc".into(),
+				to: "cab".into(),
+			},
+		]);
+		let encrypted = pipeline
+			.encrypt_str("{\"url\":\"https://example.com/?a=1\"}")
+			.unwrap();
+		assert_eq!(
+			pipeline.decrypt_str(&encrypted).unwrap(),
+			"{\"url\":\"https://example.com/?a=1\"}"
+		);
+	}
+
+	#[test]
+	fn test_substitute_round_trip() {
+		let keys = Keys::default();
+		for pipeline in [&keys.animekai, &keys.megaup] {
+			for step in pipeline.steps() {
+				if let Step::Substitute { .. } = step {
+					let stage = step.build().unwrap();
+					let data: Vec<u8> = (0..=255).collect();
+					assert_eq!(stage.inverse(&stage.forward(&data).unwrap()).unwrap(), data);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_url_decode() {
+		let pipeline = Pipeline::new(vec![Step::UrlEncode]);
+		assert!(matches!(
+			pipeline.decrypt(b"%FF%FE"),
+			Err(Error::UrlDecode(_))
+		));
+		assert_eq!(pipeline.decrypt(b"a%20b").unwrap(), b"a b");
+	}
+
+	#[test]
+	fn test_default_keys() {
+		let keys = Keys::default();
+		assert!(keys.validate().is_ok());
+		assert_eq!(keys.animekai.steps.len(), 14);
+		assert_eq!(*super::keys(), keys);
+	}
+
+	#[test]
+	fn test_from_toml() {
+		let keys = Keys::from_toml(
+			r#"
+			animekai = [
+				{ op = "url_encode" },
+				{ op = "rc4", key = "abc" },
+				{ op = "base64" },
+			]
+
+			[[megaup]]
+			op = "substitute"
+			from = "ab"
+			to = "ba"
+			"#,
+		)
+		.unwrap();
+		assert_eq!(keys.animekai.steps[1], Step::Rc4 { key: "abc".into() });
+		assert_eq!(
+			keys.megaup.steps,
+			vec![Step::Substitute {
+				from: "ab".into(),
+				to: "ba".into()
+			}]
+		);
+	}
+
+	#[test]
+	fn test_invalid_keys() {
+		assert!(matches!(
+			Keys::from_json(r#"{"animekai":[{"op":"rc4","key":""}],"megaup":[]}"#),
+			Err(Error::Rc4)
+		));
+		assert!(matches!(
+			Keys::from_json(
+				r#"{"animekai":[{"op":"substitute","from":"ab","to":"a"}],"megaup":[]}"#
+			),
+			Err(Error::Substitution { .. })
+		));
+		assert!(matches!(
+			Keys::from_json(
+				r#"{"animekai":[{"op":"substitute","from":"ab","to":"cd"}],"megaup":[]}"#
+			),
+			Err(Error::NotBijective)
+		));
+		assert!(matches!(
+			Keys::from_json(
+				r#"{"animekai":[{"op":"substitute","from":"aab","to":"abb"}],"megaup":[]}"#
+			),
+			Err(Error::NotBijective)
+		));
+		assert!(matches!(
+			Keys::from_json(r#"{"animekai":[{"op":"xor"}],"megaup":[]}"#),
+			Err(Error::Json(_))
+		));
+		assert!(matches!(
+			Pipeline::new(vec![Step::Rc4 { key: String::new() }]).decrypt(b"data"),
+			Err(Error::Rc4)
+		));
+	}
+}
@@ -1,62 +1,57 @@
-use crate::ciphers::rc4::{decode_url_safe_base64, rc4, replace, reverse, url_safe_base64};
+use crate::{pipeline, Error};
 
-pub fn encrypt(input: &str) -> String {
-	let text = urlencoding::encode(input).into_owned();
-	url_safe_base64(&replace(
-		&url_safe_base64(&rc4(
-			"sXmH96C4vhRrgi8",
-			&reverse(&reverse(&url_safe_base64(&rc4(
-				"kOCJnByYmfI",
-				&replace(
-					&replace(
-						&reverse(&url_safe_base64(&rc4("0DU8ksIVlFcia2", &text))),
-						"1wctXeHqb2",
-						"1tecHq2Xbw",
-					),
-					"48KbrZx1ml",
-					"Km8Zb4lxr1",
-				),
-			)))),
-		)),
-		"hTn79AMjduR5",
-		"djn5uT7AMR9h",
-	))
+pub fn encrypt(input: &str) -> Result<String, Error> {
+	pipeline::keys().animekai.encrypt_str(input)
 }
 
-pub fn decrypt(input: &str) -> String {
-	let text = rc4(
-		"0DU8ksIVlFcia2",
-		&decode_url_safe_base64(&reverse(&replace(
-			&replace(
-				&rc4(
-					"kOCJnByYmfI",
-					&decode_url_safe_base64(&reverse(&reverse(&rc4(
-						"sXmH96C4vhRrgi8",
-						&decode_url_safe_base64(&replace(
-							&decode_url_safe_base64(input),
-							"djn5uT7AMR9h",
-							"hTn79AMjduR5",
-						)),
-					)))),
-				),
-				"Km8Zb4lxr1",
-				"48KbrZx1ml",
-			),
-			"1tecHq2Xbw",
-			"1wctXeHqb2",
-		))),
-	);
-	urlencoding::decode(&text).unwrap().into_owned()
+pub fn decrypt(input: &str) -> Result<String, Error> {
+	pipeline::keys()
+		.animekai
+		.decrypt_json("animekai", input, &["url"])
 }
 
 #[test]
 fn test_decrypt() {
 	let decrypted = decrypt("UVJNWkZQbWl0WnRfN0lVdUFXajFKYXE5enpCcTN6Nm9rcTc5UW1ta1JIeldoUnBjYkJFUmNHME9rSFBfVzZTSTY0VUpaZHFOcFo2dFVLV19lQ2lUWmVnVmwtWTNDS0kxeHlWcmxPbzV0UUo0ajVMeXJMclRyQTZiMURieHJBd1p5MmZOdl9KRGs0bzhWYVVyQ3VYeTZoeDc1T2ZKX2dUbzJOTE04a3JkTzJHSEtkZzVjWV9JR2xCblM4QjVYR1BkLThZSDY4cFloU0stWGM0ZElaNk5hRmN2QzBuRW9DQkU5WklISzN3b3dhSXVIOHVXWW5FamN2ZnNwZ3pFZG1INDN5TUg4VzdpNDV1UE5fQUptN2Z3YlYtdEZLbm83RmZ2SWtXNndmQ0JMZnJEamQ5NUFRamJvUTdySTBodlBJRzBocnJ5MnZ3aHAtLWFuSzd0ZUxmTDlMWkRwV0NuWlV2RlBLamw2UGdobk1iMGFDTkhzd0RFTk5va0J3bEN4YTFIMDM2Qm92RkN5UnBod19iaE1WZEZzQnJ2Mk9QcWlzTm9aZFFD");
-	println!("{}", decrypted);
+	assert_eq!(
+		decrypted.unwrap(),
+		r#"{"url":"https:\/\/megaup.cc\/e\/m4TpJT_1WS2JcOLxE7xC7xvpCQ","skip":{"intro":[103,192],"outro":[1325,1419]}}"#
+	);
 }
 
 #[test]
 fn test_encrypt() {
-	let encrypted = encrypt(r#"{"url":"https:\/\/megaup.cc\/e\/2MivLzL-WS2JcOLxE7xN6hfpCQ","skip":{"intro":[91,180],"outro":[1325,1414]}}"#);
-	println!("{}", encrypted);
-}
\ No newline at end of file
+	let encrypted = encrypt(
+		r#"{"url":"https:\/\/megaup.cc\/e\/2MivLzL-WS2JcOLxE7xN6hfpCQ","skip":{"intro":[91,180],"outro":[1325,1414]}}"#,
+	);
+	assert_eq!(
+		encrypted.unwrap(),
+		"V3BFMUdfaXM4cU5fTnNodWwxNTE5b3FRM3pGQWltMlBzNHRLQlNPMDQyclh1T0p3N1dGelhIUWZzQUE4U1ltbjFwZzVXOXFrMVpSeVE1YWFJeTZUYU9kUDRxbDRjb1RZM2pWc3JzVVQ4VEo0TUw2YXROemwweTJkUkM1SnlRNDB3MG13dF9CVjZ0VF9EcFFtQy0zUThoazY1T0FiNmQ4MjNjblc1VGJYVFhhRUxCNzRNX21NalYxMGotaG5ZRW5ZaHY0bThvdFkwejI0Y3N1d0laU0pjWG9nZUd2NzJ3VnFRcjlaTDM3cDJhMHo3ODdmWVNjZkw4TEpwanlqbUhHUDJrSkwybnN3MGF1ZUo4NU5pc0hWRFZxT2pvejNHQ0hrUEVBS19SZVNXb25iakZzRkFTM3Fwd2R2R1NKVUk4eXk2cC1yOGZ3b2xlZVc4NjdMZTdYTEZ2cHJnd09tTW4ycGZZQWhyZUltX09DNFFRMHh2d0RZajhRTklqQlQxSTl1ZENXTTB2N092OGtuMzdmeVpkaFM1Qy1ZMUpmVnNoeDdaanM"
+	);
+}
+
+#[test]
+fn test_round_trip() {
+	let input = r#"{"url":"https:\/\/megaup.cc\/e\/2MivLzL-WS2JcOLxE7xN6hfpCQ"}"#;
+	assert_eq!(decrypt(&encrypt(input).unwrap()).unwrap(), input);
+	assert!(decrypt("not base64!").is_err());
+}
+
+#[test]
+fn test_keys_outdated() {
+	let unexpected = encrypt(r#"{"result":"ok"}"#).unwrap();
+	assert!(matches!(
+		decrypt(&unexpected),
+		Err(Error::KeysOutdated {
+			scheme: "animekai",
+			source: None
+		})
+	));
+	assert!(matches!(
+		decrypt("bm90IHRoZSByaWdodCBrZXlz"),
+		Err(Error::KeysOutdated {
+			scheme: "animekai",
+			source: Some(_)
+		})
+	));
+}
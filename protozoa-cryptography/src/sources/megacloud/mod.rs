@@ -0,0 +1,200 @@
+#[cfg(feature = "js-runtime")]
+mod runtime;
+
+use std::{future::Future, sync::RwLock, time::Instant};
+
+use anyhow::Context as _;
+use lazy_static::lazy_static;
+use reqwest::{header, ClientBuilder};
+
+use crate::{openssl, Error};
+
+pub const DEFAULT_ORIGINS: &[&str] = &["https://megacloud.tv", "https://megacloud.blog"];
+
+// Key derivation is the slowest step of a HiAnime source, so it's timed per stage (`meta`, `wasm`
+// and `js`) and as a whole, and counted by outcome through the `metrics` facade.
+pub const RABBIT_DURATION: &str = "protozoa_megacloud_rabbit_duration_seconds";
+// Kept apart from the stages so summing RABBIT_DURATION doesn't count every derivation twice.
+pub const RABBIT_TOTAL_DURATION: &str = "protozoa_megacloud_rabbit_total_duration_seconds";
+pub const RABBIT_TOTAL: &str = "protozoa_megacloud_rabbit_total";
+
+lazy_static! {
+	static ref ORIGINS: RwLock<Vec<String>> = RwLock::new(
+		DEFAULT_ORIGINS
+			.iter()
+			.map(|origin| origin.to_string())
+			.collect()
+	);
+}
+
+// The MegaCloud domain that answered last, which every request tries first.
+pub fn origin() -> String {
+	origins().swap_remove(0)
+}
+
+pub fn origins() -> Vec<String> {
+	ORIGINS.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+// Points every later request at another MegaCloud domain, e.g. `https://megacloud.blog`.
+pub fn set_origin(origin: &str) {
+	set_origins(&[origin.to_string()]);
+}
+
+// Replaces the domains requests fail over between, in the order they're tried. An empty list is ignored.
+pub fn set_origins(origins: &[String]) {
+	let origins: Vec<String> = origins
+		.iter()
+		.map(|origin| origin.trim_end_matches('/').to_string())
+		.collect();
+	if !origins.is_empty() {
+		*ORIGINS.write().unwrap_or_else(|e| e.into_inner()) = origins;
+	}
+}
+
+// Runs `request` against each origin until one is reachable, and tries that one first from then on.
+pub(crate) async fn with_origins<T, F, Fut>(request: F) -> Result<T, anyhow::Error>
+where
+	F: Fn(String) -> Fut,
+	Fut: Future<Output = Result<T, anyhow::Error>>,
+{
+	let mut last = None;
+	for origin in origins() {
+		match request(origin.clone()).await {
+			Ok(value) => {
+				promote(&origin);
+				return Ok(value);
+			}
+			Err(error) if is_unreachable(&error) => {
+				tracing::warn!(%origin, error = %format!("{error:#}"), "origin is unreachable");
+				last = Some(error);
+			}
+			Err(error) => return Err(error),
+		}
+	}
+
+	let error = last.unwrap_or_else(|| anyhow::anyhow!("No origins"));
+	Err(error.context("Every MegaCloud origin failed"))
+}
+
+fn promote(origin: &str) {
+	let mut origins = ORIGINS.write().unwrap_or_else(|e| e.into_inner());
+	if let Some(index) = origins
+		.iter()
+		.position(|o| o == origin)
+		.filter(|index| *index > 0)
+	{
+		let origin = origins.remove(index);
+		tracing::info!(%origin, "switching MegaCloud origin");
+		origins.insert(0, origin);
+	}
+}
+
+// Connection failures, timeouts and gateway errors, rather than anything the origin itself answered.
+fn is_unreachable(error: &anyhow::Error) -> bool {
+	error.downcast_ref::<reqwest::Error>().is_some_and(|error| {
+		error.is_connect()
+			|| error.is_timeout()
+			|| error
+				.status()
+				.is_some_and(|status| status.is_server_error())
+	})
+}
+
+#[derive(Debug)]
+pub struct Rabbit {
+	pub secret: String,
+	pub pid: String,
+	pub kversion: String,
+	pub kid: String,
+	pub browser_version: String,
+}
+
+fn elapsed_ms(started: Instant) -> u64 {
+	u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX)
+}
+
+#[tracing::instrument(err(level = "debug"))]
+pub async fn rabbit(xrax: &str) -> Result<Rabbit, anyhow::Error> {
+	#[cfg(feature = "js-runtime")]
+	{
+		let started = Instant::now();
+		let rabbit = runtime::rabbit(xrax).await;
+		let outcome = match &rabbit {
+			Ok(_) => "ok",
+			Err(_) => "error",
+		};
+		metrics::counter!(RABBIT_TOTAL, "outcome" => outcome).increment(1);
+		metrics::histogram!(RABBIT_TOTAL_DURATION, "outcome" => outcome).record(started.elapsed());
+		return rabbit;
+	}
+
+	#[cfg(not(feature = "js-runtime"))]
+	anyhow::bail!(
+		"Deriving MegaCloud keys for {xrax} requires the `js-runtime` feature, use `get_sources_with` to supply them instead"
+	)
+}
+
+#[tracing::instrument(err(level = "debug"))]
+pub async fn get_sources(xrax: String) -> Result<(String, String), anyhow::Error> {
+	let rab = rabbit(&xrax).await?;
+	get_sources_with(&xrax, rab).await
+}
+
+#[tracing::instrument(skip(rab), fields(status = tracing::field::Empty), err(level = "debug"))]
+pub async fn get_sources_with(xrax: &str, rab: Rabbit) -> Result<(String, String), anyhow::Error> {
+	let query = format!(
+		"id={}&v={}&h={}&b={}",
+		rab.pid, rab.kversion, rab.kid, rab.browser_version
+	);
+	let query = &query;
+
+	let text = with_origins(|origin| async move {
+		let client = ClientBuilder::new()
+			.default_headers({
+				let mut headers = header::HeaderMap::new();
+				headers.insert(
+					header::REFERER,
+					header::HeaderValue::from_str(&format!("{origin}/embed-2/e-1/{xrax}"))
+						.context("Failed to create referer")?,
+				);
+				headers.insert(
+					header::USER_AGENT,
+					header::HeaderValue::from_static(
+						"Mozilla/5.0 (X11; Linux x86_64; rv:133.0) Gecko/20100101 Firefox/133.0",
+					),
+				);
+				headers.insert(
+					"X-Requested-With",
+					header::HeaderValue::from_static("XMLHttpRequest"),
+				);
+				headers
+			})
+			.build()?;
+
+		let url = format!("{origin}/embed-2/ajax/e-1/getSources?{query}");
+		let started = Instant::now();
+		let response = client.get(&url).send().await?.error_for_status()?;
+		let status = response.status();
+		tracing::Span::current().record("status", status.as_u16());
+		let text = response.text().await?;
+		// The query is the derived key material, so only the path is logged.
+		tracing::debug!(
+			url = %format!("{origin}/embed-2/ajax/e-1/getSources"),
+			status = status.as_u16(),
+			elapsed_ms = elapsed_ms(started),
+			"response"
+		);
+		tracing::trace!(target: crate::BODY_TARGET, body = %text, "getSources");
+		Ok(text)
+	})
+	.await?;
+
+	Ok((text, rab.secret))
+}
+
+#[tracing::instrument(skip_all, fields(bytes = ciphertext_b64.len()), err(level = "debug"))]
+pub fn decrypt(ciphertext_b64: &str, secret: &str) -> Result<String, Error> {
+	let decrypted = openssl::Config::default().decrypt_base64(ciphertext_b64, secret.as_bytes())?;
+	String::from_utf8(decrypted).map_err(Error::Utf8)
+}
@@ -0,0 +1,140 @@
+use std::time::Instant;
+
+use anyhow::Context as _;
+use regex::Regex;
+use reqwest::{header, ClientBuilder};
+use rustyscript::{json_args, Module, ModuleWrapper};
+use sha2::{Digest as _, Sha256};
+
+use super::Rabbit;
+
+// Vendored copy of the key derivation script, pinned so a modified file is never executed.
+const RABBIT_JS: &str = include_str!("../../../rabbit.js");
+const RABBIT_JS_SHA256: &str = "eec98344aa326cd990bb5abc68ed6cb727bf4b52e8f84083470238cfbfe3cbe1";
+
+#[tracing::instrument]
+async fn get_wasm() -> Result<Vec<u8>, anyhow::Error> {
+	let client = ClientBuilder::new()
+		.default_headers({
+			let mut headers = header::HeaderMap::new();
+			headers.insert(
+				header::REFERER,
+				header::HeaderValue::from_static("https://hianime.to"),
+			);
+			headers
+		})
+		.build()?;
+	let client = &client;
+
+	let started = Instant::now();
+	let response = super::with_origins(|origin| async move {
+		let url = format!("{origin}/images/loading.png?v=0.0.9");
+		Ok(client.get(url).send().await?.error_for_status()?)
+	})
+	.await?;
+	let status = response.status();
+	let res = response.bytes().await?;
+	tracing::debug!(
+		status = status.as_u16(),
+		elapsed_ms = super::elapsed_ms(started),
+		bytes = res.len(),
+		"response"
+	);
+
+	Ok(res.to_vec())
+}
+
+#[tracing::instrument]
+async fn get_meta(xrax: &str) -> Result<String, anyhow::Error> {
+	let client = ClientBuilder::new()
+		.default_headers({
+			let mut headers = header::HeaderMap::new();
+			headers.insert(
+				header::REFERER,
+				header::HeaderValue::from_static("https://hianime.to"),
+			);
+			headers
+		})
+		.build()?;
+	let client = &client;
+
+	let started = Instant::now();
+	let response = super::with_origins(|origin| async move {
+		let url = format!("{origin}/embed-2/e-1/{xrax}");
+		Ok(client.get(url).send().await?.error_for_status()?)
+	})
+	.await?;
+	let status = response.status();
+	let html = response.text().await?;
+	tracing::debug!(
+		status = status.as_u16(),
+		elapsed_ms = super::elapsed_ms(started),
+		"response"
+	);
+	tracing::trace!(target: crate::BODY_TARGET, body = %html, "embed");
+
+	let meta = Regex::new(r#"<meta name="j_crt" content="(.+?)">"#)?;
+	let content = &meta.captures(&html).context("Failed to get meta")?[1];
+
+	Ok(content.to_string())
+}
+
+fn verify_script(script: &str) -> Result<(), anyhow::Error> {
+	let digest = Sha256::digest(script.as_bytes());
+	let hash: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+	anyhow::ensure!(
+		hash == RABBIT_JS_SHA256,
+		"rabbit.js does not match the pinned hash ({hash})"
+	);
+
+	Ok(())
+}
+
+pub(super) async fn rabbit(xrax: &str) -> Result<Rabbit, anyhow::Error> {
+	verify_script(RABBIT_JS)?;
+
+	let started = Instant::now();
+	let meta = get_meta(xrax).await?;
+	record("meta", started);
+	let started = Instant::now();
+	let wasm = get_wasm().await?;
+	record("wasm", started);
+
+	let xrax = xrax.to_string();
+	let started = Instant::now();
+	let result = tokio::task::spawn_blocking(move || -> Result<Rabbit, anyhow::Error> {
+		let module = Module::new("rabbit.js", RABBIT_JS);
+
+		let mut module_wrapper = ModuleWrapper::new_from_module(&module, Default::default())?;
+
+		let values: (String, String, String, String, String) =
+			module_wrapper.call("get_args", json_args!(xrax, meta, wasm))?;
+
+		Ok(Rabbit {
+			secret: values.0,
+			pid: values.1,
+			kversion: values.2,
+			kid: values.3,
+			browser_version: values.4,
+		})
+	});
+
+	let rabbit = result.await?;
+	record("js", started);
+	tracing::debug!(
+		elapsed_ms = super::elapsed_ms(started),
+		ok = rabbit.is_ok(),
+		"derived keys"
+	);
+	rabbit
+}
+
+fn record(stage: &'static str, started: Instant) {
+	metrics::histogram!(super::RABBIT_DURATION, "stage" => stage).record(started.elapsed());
+}
+
+#[test]
+fn test_rabbit_pinned() {
+	verify_script(RABBIT_JS).unwrap();
+	assert!(verify_script(&RABBIT_JS.replace("groot", "gr00t")).is_err());
+}
@@ -1,3 +1,6 @@
+#[cfg(feature = "animekai")]
 pub mod megaup;
+#[cfg(feature = "animekai")]
 pub mod animekai;
-pub mod megacloud;
\ No newline at end of file
+#[cfg(feature = "megacloud")]
+pub mod megacloud;
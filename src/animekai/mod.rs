@@ -1,15 +1,19 @@
-use crate::{Caption, Episode, Locale, SearchResult, Server, Source};
+use crate::{
+	antibot, extractors, format_label,
+	mirrors::{self, Site},
+	AnimeId, Caption, Episode, EpisodeId, Locale, Provider, Related, Relation, SearchResult,
+	Server, ServerId, Source,
+};
 use anyhow::Context as _;
 use kuchikiki::traits::*;
 use protozoa_cryptography::sources::{animekai, megaup};
 use serde_json::Value;
 
 pub async fn search(query: &str) -> Result<Vec<SearchResult>, anyhow::Error> {
-	let json: Value = reqwest::get(format!(
-		"https://animekai.to/ajax/anime/search?keyword={query}"
-	))
-	.await?
-	.json()
+	let json: Value = mirrors::json(
+		Site::AnimeKai,
+		&format!("/ajax/anime/search?keyword={query}"),
+	)
 	.await?;
 
 	let html = json["result"]["html"].as_str().context("No result")?;
@@ -37,7 +41,7 @@ pub async fn search(query: &str) -> Result<Vec<SearchResult>, anyhow::Error> {
 			SearchResult {
 				title,
 				poster: poster.to_string(),
-				id: id.to_string(),
+				id: AnimeId::new(Provider::AnimeKai, id),
 			}
 		})
 		.collect();
@@ -45,32 +49,114 @@ pub async fn search(query: &str) -> Result<Vec<SearchResult>, anyhow::Error> {
 	Ok(results)
 }
 
-pub async fn episodes(id: &str) -> Result<Vec<Episode>, anyhow::Error> {
-	let html = reqwest::get(format!("https://animekai.to/watch/{id}"))
-		.await?
-		.text()
-		.await?;
+pub async fn related(id: &str) -> Result<Vec<Related>, anyhow::Error> {
+	let html = mirrors::text(Site::AnimeKai, &format!("/watch/{id}")).await?;
+
+	parse_related(&html, id)
+}
 
+fn parse_related(html: &str, id: &str) -> Result<Vec<Related>, anyhow::Error> {
 	let document = kuchikiki::parse_html().one(html);
-	let bookmark = document
-		.select_first(".user-bookmark")
-		.map_err(|_| anyhow::anyhow!("No bookmark"))?;
+	let mut related = Vec::new();
+
+	for (selector, section) in [
+		("#seasons .aitem", Some(Relation::Season)),
+		("#related-anime .aitem", None),
+	] {
+		let items = document
+			.select(selector)
+			.map_err(|_| anyhow::anyhow!("Failed to select related"))?;
+		for item in items {
+			// Season items wrap their link, related items are the link.
+			let link = match item.attributes.borrow().contains("href") {
+				true => item.clone(),
+				false => item
+					.as_node()
+					.select_first("a")
+					.map_err(|_| anyhow::anyhow!("No related link"))?,
+			};
+			let href = link
+				.attributes
+				.borrow()
+				.get("href")
+				.context("No related link")?
+				.to_string();
+			let Some((_, key)) = href.split('?').next().unwrap_or(&href).rsplit_once('-') else {
+				continue;
+			};
+
+			let poster = item.as_node().select_first("img").ok().and_then(|img| {
+				let attributes = img.attributes.borrow();
+				attributes
+					.get("data-src")
+					.or_else(|| attributes.get("src"))
+					.map(str::to_string)
+			});
+			let title = item
+				.as_node()
+				.select_first(".title")
+				.map(|title| title.text_contents())
+				.or_else(|_| {
+					item.as_node().select_first("img").map(|img| {
+						let alt = img.attributes.borrow().get("alt").map(str::to_string);
+						alt.unwrap_or_default()
+					})
+				})
+				.unwrap_or_default();
+
+			// Related items label the relation, like `Sequel`, next to their format.
+			let labels: Vec<String> = item
+				.as_node()
+				.select(".info span")
+				.into_iter()
+				.flatten()
+				.map(|span| span.text_contents())
+				.collect();
+			let relation = section.unwrap_or_else(|| {
+				labels
+					.iter()
+					.map(|label| Relation::from_label(label))
+					.find(|relation| *relation != Relation::Other)
+					.unwrap_or(Relation::Other)
+			});
+			let format = labels.iter().find_map(|label| format_label(label));
+
+			related.push(Related {
+				title: title.trim().to_string(),
+				poster,
+				relation,
+				format,
+				id: AnimeId::new(Provider::AnimeKai, key),
+			});
+		}
+	}
+
+	related.retain(|entry| entry.id.key != id);
+	Ok(related)
+}
+
+pub async fn episodes(id: &str) -> Result<Vec<Episode>, anyhow::Error> {
+	let html = mirrors::text(Site::AnimeKai, &format!("/watch/{id}")).await?;
 
 	let bookmark_id = {
+		let document = kuchikiki::parse_html().one(html);
+		let bookmark = document
+			.select_first(".user-bookmark")
+			.map_err(|_| anyhow::anyhow!("No bookmark"))?;
 		let attributes = bookmark.attributes.borrow();
 		attributes.get("data-id").unwrap().to_string()
 	};
 
-	let enc_id = animekai::encrypt(&bookmark_id);
+	let enc_id = animekai::encrypt(&bookmark_id)?;
 
-	let json: Value = reqwest::get(format!(
-		"https://animekai.to/ajax/episodes/list?ani_id={bookmark_id}&_={enc_id}"
-	))
-	.await?
-	.json()
+	let json: Value = mirrors::json(
+		Site::AnimeKai,
+		&format!("/ajax/episodes/list?ani_id={bookmark_id}&_={enc_id}"),
+	)
 	.await?;
 
 	let html = json["result"].as_str().context("No result")?;
+	let anime = AnimeId::new(Provider::AnimeKai, id);
 	let document = kuchikiki::parse_html().one(html);
 	let episodes = document
 		.select("a")
@@ -79,19 +165,36 @@ pub async fn episodes(id: &str) -> Result<Vec<Episode>, anyhow::Error> {
 	let episode_list = episodes
 		.map(|episode| {
 			let attributes = episode.attributes.borrow();
-			let id = attributes.get("token").context("No token")?;
-			let title = episode
+			let token = attributes.get("token").context("No token")?;
+			let span = episode
 				.as_node()
 				.select_first("span")
-				.map_err(|_| anyhow::anyhow!("No title"))?
-				.text_contents();
-
-			let number = attributes.get("num").unwrap().parse().unwrap();
+				.map_err(|_| anyhow::anyhow!("No title"))?;
+			let title = span.text_contents();
+			let japanese_title = span
+				.attributes
+				.borrow()
+				.get("data-jp")
+				.filter(|jp| !jp.is_empty())
+				.map(str::to_string);
+
+			let number = attributes
+				.get("num")
+				.context("No episode number")?
+				.parse()?;
+			let id = EpisodeId::with_token(anime.clone(), number, token);
+			let filler = attributes
+				.get("class")
+				.is_some_and(|class| class.contains("filler"));
+			// Bit 1 is sub and bit 2 is dub.
+			let langs: Option<u8> = attributes.get("langs").and_then(|langs| langs.parse().ok());
 
 			Ok(Episode {
-				id: id.to_string(),
-				title,
-				number,
+				japanese_title,
+				filler: Some(filler),
+				sub: langs.map(|langs| langs & 1 != 0),
+				dub: langs.map(|langs| langs & 2 != 0),
+				..Episode::new(id, title)
 			})
 		})
 		.collect::<Result<Vec<Episode>, anyhow::Error>>()?;
@@ -99,69 +202,82 @@ pub async fn episodes(id: &str) -> Result<Vec<Episode>, anyhow::Error> {
 	Ok(episode_list)
 }
 
-pub async fn servers(token: &str) -> Result<Vec<Server>, anyhow::Error> {
-	let enc_token = animekai::encrypt(token);
+pub async fn servers(id: &EpisodeId, token: &str) -> Result<Vec<Server>, anyhow::Error> {
+	let enc_token = animekai::encrypt(token)?;
 
-	let json: Value = reqwest::get(format!(
-		"https://animekai.to/ajax/links/list?token={token}&_={enc_token}"
-	))
-	.await?
-	.json()
+	let json: Value = mirrors::json(
+		Site::AnimeKai,
+		&format!("/ajax/links/list?token={token}&_={enc_token}"),
+	)
 	.await?;
 
 	let html = json["result"].as_str().context("No result")?;
 
-	let document = kuchikiki::parse_html().one(html);
-	let servers = document
-		.select(".server")
-		.map_err(|_| anyhow::anyhow!("No servers"))?;
+	let servers: Vec<(String, String, Locale)> = {
+		let document = kuchikiki::parse_html().one(html);
+		document
+			.select(".server")
+			.map_err(|_| anyhow::anyhow!("No servers"))?
+			// Servers under a group we don't know the locale of are skipped rather than guessed.
+			.filter_map(|server| {
+				let parent = server.as_node().parent()?;
+				let locale = match parent.as_element()?.attributes.borrow().get("data-id") {
+					Some("sub") => Locale::HardSub,
+					Some("dub") => Locale::Dub,
+					Some("softsub") => Locale::SoftSub,
+					other => {
+						tracing::debug!(locale = ?other, "skipping server with unknown locale");
+						return None;
+					}
+				};
+
+				let attributes = server.attributes.borrow();
+				let name = server.text_contents();
+				let lid = attributes.get("data-lid")?.to_string();
+
+				Some((name, lid, locale))
+			})
+			.collect()
+	};
 
 	let mut server_list = Vec::new();
-	for server in servers {
-		let parent = server.as_node().parent().unwrap();
-		let attributes = parent.as_element().unwrap().attributes.borrow();
-
-		let id = attributes.get("data-id").unwrap();
-		let locale = match id {
-			"sub" => Locale::HardSub,
-			"dub" => Locale::Dub,
-			"softsub" => Locale::SoftSub,
-			_ => unimplemented!("Unknown locale"),
-		};
-
-		let attributes = server.attributes.borrow();
-		let name = server.text_contents();
-
-		let lid = attributes.get("data-lid").unwrap();
-		let enc_lid = animekai::encrypt(lid);
-
-		let json: Value = reqwest::get(format!(
-			"https://animekai.to/ajax/links/view?id={lid}&_={enc_lid}"
-		))
-		.await?
-		.json()
+	for (name, lid, locale) in servers {
+		let enc_lid = animekai::encrypt(&lid)?;
+
+		let json: Value = mirrors::json(
+			Site::AnimeKai,
+			&format!("/ajax/links/view?id={lid}&_={enc_lid}"),
+		)
 		.await?;
 
 		let result = json["result"].as_str().context("No result")?;
-		let json: Value = serde_json::from_str(&animekai::decrypt(result))?;
+		let json: Value = serde_json::from_str(&animekai::decrypt(result)?)?;
 		let url = json["url"].as_str().context("No url")?.to_string();
 
 		let name = format!("{name} · {locale}");
-
-		server_list.push(Server { name, url, locale });
+		let id = ServerId::new(id.clone(), &name);
+
+		server_list.push(Server {
+			name,
+			url,
+			locale,
+			id,
+		});
 	}
 
 	Ok(server_list)
 }
 
 pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
-	let json: Value = reqwest::get(url.replace("/e/", "/media/"))
-		.await?
-		.json()
-		.await?;
+	if extractors::is_supported(url) {
+		return extractors::get_source(url).await;
+	}
+
+	let headers = extractors::referer_headers(url)?;
+	let json: Value = antibot::json(&url.replace("/e/", "/media/")).await?;
 
 	let result = json["result"].as_str().context("No result")?;
-	let decrypted = megaup::decrypt(result);
+	let decrypted = megaup::decrypt(result)?;
 	let json: Value = serde_json::from_str(&decrypted)?;
 
 	let url = json["sources"][0]["file"].as_str().context("No file")?;
@@ -171,6 +287,7 @@ pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
 	Ok(Source {
 		url: url.to_string(),
 		captions,
+		headers,
 	})
 }
 
@@ -181,7 +298,7 @@ mod tests {
 	#[tokio::test]
 	async fn test_search() {
 		let results = search("One Piece").await.unwrap();
-		assert_eq!(results[0].id, "dk6r");
+		assert_eq!(results[0].id.key, "dk6r");
 		assert!(!results.is_empty(), "Results should not be empty");
 	}
 
@@ -191,15 +308,61 @@ mod tests {
 		assert!(!episodes.is_empty(), "Episodes should not be empty");
 	}
 
+	#[test]
+	fn test_parse_related() {
+		let html = r#"
+			<section id="seasons">
+				<div class="aitem active"><a class="poster" href="/watch/jujutsu-kaisen-4gm6"><img src="https://img/1.jpg" alt="Season 1"></a></div>
+				<div class="aitem"><a class="poster" href="/watch/jujutsu-kaisen-season-2-73v2"><img src="https://img/2.jpg" alt="Season 2"></a></div>
+			</section>
+			<section id="related-anime">
+				<a class="aitem" href="/watch/jujutsu-kaisen-0-movie-x1q5">
+					<div class="title">Jujutsu Kaisen 0</div>
+					<div class="info"><span><b>Prequel</b></span><span><b>Movie</b></span></div>
+				</a>
+				<a class="aitem" href="/watch/jujutsu-kaisen-shibuya-recap-9pk0">
+					<div class="title">Shibuya Incident Recap</div>
+					<div class="info"><span>Special</span></div>
+				</a>
+			</section>
+		"#;
+
+		let related = parse_related(html, "4gm6").unwrap();
+		let relations: Vec<_> = related
+			.iter()
+			.map(|r| (r.id.key.as_str(), r.relation, r.format.as_deref()))
+			.collect();
+		assert_eq!(
+			relations,
+			[
+				("73v2", Relation::Season, None),
+				("x1q5", Relation::Prequel, Some("Movie")),
+				("9pk0", Relation::Other, Some("Special"))
+			]
+		);
+		assert_eq!(related[0].title, "Season 2");
+		assert_eq!(related[1].title, "Jujutsu Kaisen 0");
+	}
+
+	#[tokio::test]
+	async fn test_related() {
+		let related = related("dk6r").await.unwrap();
+		assert!(!related.is_empty(), "Related should not be empty");
+	}
+
+	fn episode() -> EpisodeId {
+		EpisodeId::new(AnimeId::new(Provider::AnimeKai, "dk6r"), 1.into())
+	}
+
 	#[tokio::test]
 	async fn test_servers() {
-		let servers = servers("ccTwp_Hxokjv02gVx4if").await.unwrap();
+		let servers = servers(&episode(), "ccTwp_Hxokjv02gVx4if").await.unwrap();
 		assert!(!servers.is_empty(), "Servers should not be empty");
 	}
 
 	#[tokio::test]
 	async fn test_get_source() {
-		let servers = servers("ccTwp_Hxokjv02gVx4if").await.unwrap();
+		let servers = servers(&episode(), "ccTwp_Hxokjv02gVx4if").await.unwrap();
 		assert!(!servers.is_empty(), "Can't test source without servers");
 
 		println!("{:#?}", servers);
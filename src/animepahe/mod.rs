@@ -1,73 +1,121 @@
-mod unpacker;
-
-use crate::{Episode, Locale, SearchResult, Server, Source};
+use crate::{
+	antibot::ddos_guard,
+	deobfuscate, extractors,
+	mirrors::{self, Site},
+	AnimeId, Episode, EpisodeId, EpisodeNumber, Locale, Provider, SearchResult, Server, ServerId,
+	Source,
+};
 use anyhow::Context as _;
-use futures::{stream, StreamExt as _};
+use futures::{stream, Stream, StreamExt as _, TryStreamExt as _};
 use kuchikiki::traits::*;
 use regex::Regex;
-use reqwest::{header, Client};
+use serde::{de::DeserializeOwned, Deserialize};
 use serde_json::Value;
+use std::{collections::BTreeMap, ops::RangeInclusive, sync::Arc};
 
-async fn create_client() -> Result<Client, anyhow::Error> {
-	let res = reqwest::get("https://check.ddos-guard.net/check.js").await?;
-	let headers = res.headers();
-
-	let etag_header = headers.get(header::ETAG).context("ETAG not found")?;
-	let ddos_cookie = format!("__ddg2_={};", etag_header.to_str()?);
-
-	let client = Client::builder()
-		.default_headers({
-			let mut headers = header::HeaderMap::new();
-			headers.insert(
-				header::COOKIE,
-				header::HeaderValue::from_str(&ddos_cookie)?,
-			);
-			headers.insert(
-				header::REFERER,
-				header::HeaderValue::from_static("https://animepahe.ru/"),
-			);
-			headers.insert(
-				header::USER_AGENT,
-				header::HeaderValue::from_static(
-					"Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.3",
-				),
-			);
-			headers
-		})
-		.build()?;
+#[derive(Deserialize)]
+struct SearchItem {
+	id: u64,
+	title: String,
+	poster: String,
+}
 
-	Ok(client)
+#[derive(Deserialize)]
+struct Release {
+	episode: EpisodeNumber,
+	session: String,
+	#[serde(default)]
+	title: String,
+	#[serde(default)]
+	filler: u8,
+	snapshot: Option<String>,
+	duration: Option<String>,
+	created_at: Option<String>,
+	audio: Option<String>,
 }
 
-pub async fn search(query: &str) -> Result<Vec<SearchResult>, anyhow::Error> {
-	let client = create_client().await?;
-	let json: Value = client
-		.get(format!("https://animepahe.ru/api?m=search&q={query}"))
-		.send()
-		.await?
-		.json()
-		.await?;
-
-	let data: Vec<SearchResult> = serde_json::from_value(json["data"].clone())?;
-	Ok(data)
+impl Release {
+	fn into_episode(self, anime: &AnimeId, session: &str) -> Episode {
+		let token = format!("{session}/{}", self.session);
+		let id = EpisodeId::with_token(anime.clone(), self.episode, token);
+		let title = match self.title.is_empty() {
+			true => format!("Episode {}", self.episode),
+			false => self.title,
+		};
+		// Only the release's own audio is listed, the other language may still be among its servers.
+		let audio = self.audio.as_deref();
+
+		Episode {
+			filler: Some(self.filler != 0),
+			air_date: self.created_at,
+			thumbnail: self.snapshot.filter(|snapshot| !snapshot.is_empty()),
+			duration: self.duration.as_deref().and_then(parse_duration),
+			sub: (audio == Some("jpn")).then_some(true),
+			dub: (audio == Some("eng")).then_some(true),
+			..Episode::new(id, title)
+		}
+	}
 }
 
-pub async fn episodes(id: &str) -> Result<Vec<Episode>, anyhow::Error> {
-	let client = create_client().await?;
-	let html = client
-		.get(format!("https://animepahe.ru/a/{id}"))
-		.send()
-		.await?
-		.text()
-		.await?;
+// `hh:mm:ss` as seconds.
+fn parse_duration(duration: &str) -> Option<u32> {
+	duration.split(':').try_fold(0, |total, part| {
+		Some(total * 60 + part.parse::<u32>().ok()?)
+	})
+}
 
-	let document = kuchikiki::parse_html().one(html);
-	let script = document
-		.select("script")
-		.map_err(|_| anyhow::anyhow!("script not found"))?
-		.find(|x| x.text_contents().contains("let id ="))
-		.context("Failed to get anime data")?
-		.text_contents();
+fn create_client() -> Result<ddos_guard::Client, anyhow::Error> {
+	ddos_guard::Client::new(&mirrors::get(Site::AnimePahe))
+}
+
+// GETs `path` from whichever AnimePahe mirror is up.
+async fn text(client: &ddos_guard::Client, path: &str) -> Result<String, anyhow::Error> {
+	let page = mirrors::fetch(Site::AnimePahe, path, |url| async move {
+		client.get(&url).await
+	})
+	.await?;
+	anyhow::ensure!(page.status.is_success(), "{path} returned {}", page.status);
+	Ok(page.body)
+}
+
+async fn json<T: DeserializeOwned>(
+	client: &ddos_guard::Client, path: &str,
+) -> Result<T, anyhow::Error> {
+	let text = text(client, path).await?;
+	serde_json::from_str(&text).with_context(|| format!("Failed to parse {path}"))
+}
+
+pub async fn search(query: &str) -> Result<Vec<SearchResult>, anyhow::Error> {
+	let client = create_client()?;
+	let json: Value = json(&client, &format!("/api?m=search&q={query}")).await?;
+
+	let items: Vec<SearchItem> = serde_json::from_value(json["data"].clone())?;
+	let results = items
+		.into_iter()
+		.map(|item| SearchResult {
+			title: item.title,
+			poster: item.poster,
+			id: AnimeId::new(Provider::AnimePahe, item.id.to_string()),
+		})
+		.collect();
+
+	Ok(results)
+}
+
+// The release api is keyed by the anime's session, which only appears in its page.
+async fn session(id: &str) -> Result<String, anyhow::Error> {
+	let client = create_client()?;
+	let html = text(&client, &format!("/a/{id}")).await?;
+
+	let script = {
+		let document = kuchikiki::parse_html().one(html);
+		document
+			.select("script")
+			.map_err(|_| anyhow::anyhow!("script not found"))?
+			.find(|x| x.text_contents().contains("let id ="))
+			.context("Failed to get anime data")?
+			.text_contents()
+	};
 
 	let re = Regex::new(r#"let id = "(.*)";"#).expect("Failed to compile regex");
 	let session = re
@@ -77,84 +125,179 @@ pub async fn episodes(id: &str) -> Result<Vec<Episode>, anyhow::Error> {
 		.context("Failed to get session")?
 		.as_str();
 
-	let client = create_client().await?;
-	let json: Value = client
-		.get(format!(
-			"https://animepahe.ru/api?m=release&id={session}&page=1"
-		))
-		.send()
-		.await?
-		.json()
-		.await?;
-
-	let last_page = json["last_page"]
-		.as_u64()
-		.context("Failed to get last page")?;
-
-	let data: Vec<Episode> = serde_json::from_value(json["data"].clone())?;
-	let mut episodes = data;
-
-	let handles = (2..=last_page)
-		.map(async |page_num| {
-			let session = session.to_string();
-			let client = create_client().await.unwrap();
-			tokio::spawn(async move {
-				let mut retries = 3;
-				loop {
-					let res = client
-						.get(format!(
-							"https://animepahe.ru/api?m=release&id={session}&page={page_num}"
-						))
-						.send()
-						.await;
-
-					match res {
-						Ok(response) => {
-							let json: Value = response.json().await?;
-							let data: Vec<Episode> = serde_json::from_value(json["data"].clone())?;
-							return Ok::<_, anyhow::Error>(data);
-						}
-						Err(err) => {
-							if retries == 0 {
-								return Err(err.into());
-							}
-							retries -= 1;
-							tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-						}
-					}
-				}
-			})
-			.await
-		})
-		.collect::<Vec<_>>();
+	Ok(session.to_string())
+}
+
+// Long-running shows are listed per season but numbered from the first season, so e.g. season 2 starts at 13.
+#[cfg(feature = "mal")]
+pub async fn first_episode(id: &str) -> Result<EpisodeNumber, anyhow::Error> {
+	let (_, first) = Releases::open(id).await?;
+	let first = first.first().context("No releases")?;
+	Ok(first.number)
+}
 
-	let results = stream::iter(handles)
-		.buffer_unordered(10)
-		.collect::<Vec<_>>()
-		.await;
+// Pages fetched at once when listing a whole anime.
+pub const CONCURRENCY: usize = 10;
+
+// The release api of one anime, sorted by episode so every page continues the previous one.
+struct Releases {
+	client: ddos_guard::Client,
+	session: String,
+	anime: AnimeId,
+	per_page: u64,
+	last_page: u64,
+}
+
+impl Releases {
+	// Also returns the first page, since that's where the page count comes from.
+	async fn open(id: &str) -> Result<(Self, Vec<Episode>), anyhow::Error> {
+		let session = session(id).await?;
+		let client = create_client()?;
+		let json = fetch_page(&client, &session, 1).await?;
+
+		let releases = Releases {
+			client,
+			session,
+			anime: AnimeId::new(Provider::AnimePahe, id),
+			per_page: json["per_page"]
+				.as_u64()
+				.context("Failed to get page size")?,
+			last_page: json["last_page"]
+				.as_u64()
+				.context("Failed to get last page")?,
+		};
+		let first = releases.parse(&json)?;
+		Ok((releases, first))
+	}
 
-	for result in results {
-		episodes.extend(result??);
+	async fn page(&self, page: u64) -> Result<Vec<Episode>, anyhow::Error> {
+		let json = fetch_page(&self.client, &self.session, page).await?;
+		self.parse(&json)
 	}
 
+	// Fetches the pages in `range` that aren't in `pages` yet.
+	async fn fill(
+		&self, pages: &mut BTreeMap<u64, Vec<Episode>>, range: RangeInclusive<u64>,
+	) -> Result<(), anyhow::Error> {
+		let missing: Vec<u64> = range.filter(|page| !pages.contains_key(page)).collect();
+		let fetched: Vec<(u64, Vec<Episode>)> = stream::iter(missing)
+			.map(|page| async move { Ok::<_, anyhow::Error>((page, self.page(page).await?)) })
+			.buffered(CONCURRENCY)
+			.try_collect()
+			.await?;
+
+		pages.extend(fetched);
+		Ok(())
+	}
+
+	fn parse(&self, json: &Value) -> Result<Vec<Episode>, anyhow::Error> {
+		let releases: Vec<Release> = serde_json::from_value(json["data"].clone())?;
+		Ok(releases
+			.into_iter()
+			.map(|release| release.into_episode(&self.anime, &self.session))
+			.collect())
+	}
+}
+
+#[tracing::instrument(skip(client, session), fields(retries = tracing::field::Empty))]
+async fn fetch_page(
+	client: &ddos_guard::Client, session: &str, page: u64,
+) -> Result<Value, anyhow::Error> {
+	let path = format!("/api?m=release&id={session}&sort=episode_asc&page={page}");
+	let mut retries = 3;
+	loop {
+		match json(client, &path).await {
+			Ok(json) => return Ok(json),
+			Err(err) => {
+				if retries == 0 {
+					return Err(err);
+				}
+				retries -= 1;
+				tracing::Span::current().record("retries", 3 - retries);
+				tracing::debug!(error = %format!("{err:#}"), "retrying page");
+				tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+			}
+		}
+	}
+}
+
+// Page holding `number`, assuming one release per episode from `first` onwards.
+fn page_of(number: EpisodeNumber, first: EpisodeNumber, per_page: u64, last_page: u64) -> u64 {
+	let offset = u64::from(number.whole().saturating_sub(first.whole()));
+	(offset / per_page.max(1) + 1).min(last_page.max(1))
+}
+
+pub async fn episodes(id: &str) -> Result<Vec<Episode>, anyhow::Error> {
+	let mut episodes: Vec<Episode> = episodes_stream(id, CONCURRENCY).try_collect().await?;
 	episodes.sort_by_key(|episode| episode.number);
+	Ok(episodes)
+}
+
+// Yields episodes page by page in order, fetching up to `concurrency` pages ahead.
+pub fn episodes_stream(
+	id: &str, concurrency: usize,
+) -> impl Stream<Item = Result<Episode, anyhow::Error>> + Send + 'static {
+	let id = id.to_string();
+	stream::once(async move { Releases::open(&id).await })
+		.map_ok(move |(releases, first)| {
+			let releases = Arc::new(releases);
+			let rest = stream::iter(2..=releases.last_page)
+				.map(move |page| {
+					let releases = releases.clone();
+					async move { releases.page(page).await }
+				})
+				.buffered(concurrency.max(1));
+			stream::once(async { Ok(first) }).chain(rest)
+		})
+		.try_flatten()
+		.map_ok(|episodes| stream::iter(episodes.into_iter().map(Ok)))
+		.try_flatten()
+}
 
-	episodes.iter_mut().for_each(|episode| {
-		episode.title = format!("Episode {}", episode.number);
-		episode.id = format!("{session}/{}", episode.id);
-	});
+// Only fetches the pages covering `from..=to`, estimated from the page size and corrected for gaps.
+pub async fn episodes_range(
+	id: &str, from: EpisodeNumber, to: EpisodeNumber,
+) -> Result<Vec<Episode>, anyhow::Error> {
+	let (releases, first) = Releases::open(id).await?;
+	let Some(start) = first.first().map(|episode| episode.number) else {
+		return Ok(Vec::new());
+	};
+
+	let page = |number| page_of(number, start, releases.per_page, releases.last_page);
+	let (mut low, mut high) = (page(from), page(to).max(page(from)));
+	let mut pages = BTreeMap::from([(1, first)]);
+	releases.fill(&mut pages, low..=high).await?;
+
+	// Recaps and gaps shift the numbering, so widen the range until both ends are covered.
+	while low > 1
+		&& pages[&low]
+			.first()
+			.is_some_and(|episode| episode.number > from)
+	{
+		low -= 1;
+		releases.fill(&mut pages, low..=low).await?;
+	}
+	while high < releases.last_page
+		&& pages[&high]
+			.last()
+			.is_some_and(|episode| episode.number < to)
+	{
+		high += 1;
+		releases.fill(&mut pages, high..=high).await?;
+	}
 
+	let episodes = pages
+		.into_iter()
+		.filter(|(page, _)| (low..=high).contains(page))
+		.flat_map(|(_, episodes)| episodes)
+		.filter(|episode| (from..=to).contains(&episode.number))
+		.collect();
 	Ok(episodes)
 }
 
-pub async fn servers(ep_id: &str) -> Result<Vec<Server>, anyhow::Error> {
-	let client = create_client().await?;
-	let html = client
-		.get(format!("https://animepahe.ru/play/{ep_id}"))
-		.send()
-		.await?
-		.text()
-		.await?;
+pub async fn servers(id: &EpisodeId, ep_id: &str) -> Result<Vec<Server>, anyhow::Error> {
+	let client = create_client()?;
+	let html = text(&client, &format!("/play/{ep_id}")).await?;
 
 	let document = kuchikiki::parse_html().one(html);
 	let servers = document
@@ -162,19 +305,33 @@ pub async fn servers(ep_id: &str) -> Result<Vec<Server>, anyhow::Error> {
 		.map_err(|_| anyhow::anyhow!("No servers"))?;
 	let server_list: Vec<Server> = servers
 		.rev()
-		.map(|server| {
+		.filter_map(|server| {
 			let attributes = server.attributes.borrow();
-			let url = attributes.get("data-src").unwrap().to_string();
-			let fansub = attributes.get("data-fansub").unwrap();
-			let resolution = attributes.get("data-resolution").unwrap();
+			let (Some(url), Some(fansub), Some(resolution)) = (
+				attributes.get("data-src"),
+				attributes.get("data-fansub"),
+				attributes.get("data-resolution"),
+			) else {
+				tracing::debug!("skipping server with missing attributes");
+				return None;
+			};
 			let locale = match attributes.get("data-audio") {
 				Some("eng") => Locale::Dub,
 				Some("jpn") => Locale::HardSub,
-				_ => unimplemented!("Unknown locale"),
+				other => {
+					tracing::debug!(locale = ?other, "skipping server with unknown locale");
+					return None;
+				}
 			};
 
 			let name = format!("{fansub} · {resolution}p {locale}");
-			Server { name, locale, url }
+			let id = ServerId::new(id.clone(), &name);
+			Some(Server {
+				name,
+				locale,
+				url: url.to_string(),
+				id,
+			})
 		})
 		.collect();
 
@@ -182,8 +339,22 @@ pub async fn servers(ep_id: &str) -> Result<Vec<Server>, anyhow::Error> {
 }
 
 pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
-	let client = create_client().await?;
-	let html = client.get(url).send().await?.text().await?;
+	let client = create_client()?;
+	// Kwik moves domains too, so its embeds are retried on the other mirrors.
+	let (html, url) = match mirrors::path(Site::Kwik, url) {
+		Some(path) => {
+			let client = &client;
+			let page = mirrors::fetch(
+				Site::Kwik,
+				&path,
+				|url| async move { client.get(&url).await },
+			)
+			.await?;
+			anyhow::ensure!(page.status.is_success(), "{url} returned {}", page.status);
+			(page.body, format!("{}{path}", mirrors::base(Site::Kwik)))
+		}
+		None => (client.text(url).await?, url.to_string()),
+	};
 	let document = kuchikiki::parse_html().one(html);
 	let script = document
 		.select("script")
@@ -192,13 +363,17 @@ pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
 		.context("Failed to get video data")?
 		.text_contents();
 
-	let unpacked = unpacker::unpack_source(&script).context("Failed to unpack source")?;
-	let re = Regex::new(r"https://.*\.m3u8").expect("Failed to compile regex");
-	let source = re.find(&unpacked).context("Failed to get source")?.as_str();
+	let unpacked = deobfuscate::unpack(&script).context("Failed to unpack source")?;
+	let urls = deobfuscate::media_urls(&unpacked);
+	let source = urls
+		.iter()
+		.find(|url| url.contains(".m3u8"))
+		.context("Failed to get source")?;
 
 	Ok(Source {
 		url: source.to_string(),
 		captions: Vec::new(),
+		headers: extractors::referer_headers(&url)?,
 	})
 }
 
@@ -206,17 +381,35 @@ pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
 mod tests {
 	use super::*;
 
+	#[test]
+	fn test_parse_duration() {
+		assert_eq!(parse_duration("00:24:39"), Some(1479));
+		assert_eq!(parse_duration("01:02:03"), Some(3723));
+		assert_eq!(parse_duration("24:39"), Some(1479));
+		assert_eq!(parse_duration("unknown"), None);
+	}
+
+	#[test]
+	fn test_page_of() {
+		let first = EpisodeNumber::new(13);
+		assert_eq!(page_of(EpisodeNumber::new(13), first, 30, 5), 1);
+		assert_eq!(page_of(EpisodeNumber::new(42), first, 30, 5), 1);
+		assert_eq!(page_of(EpisodeNumber::new(43), first, 30, 5), 2);
+		assert_eq!(page_of(EpisodeNumber::new(1), first, 30, 5), 1);
+		assert_eq!(page_of(EpisodeNumber::new(1000), first, 30, 5), 5);
+	}
+
 	#[tokio::test]
 	async fn test_create_client() {
-		let client = create_client().await.unwrap();
-		let res = client.get("https://animepahe.ru/a/4").send().await.unwrap();
-		assert!(res.status().is_success());
+		let client = create_client().unwrap();
+		let html = client.text("https://animepahe.ru/a/4").await.unwrap();
+		assert!(!html.is_empty());
 	}
 
 	#[tokio::test]
 	async fn test_search() {
 		let results = search("One Piece").await.unwrap();
-		assert_eq!(results[0].id, "4");
+		assert_eq!(results[0].id.key, "4");
 		assert!(!results.is_empty(), "Results should not be empty");
 	}
 
@@ -226,6 +419,26 @@ mod tests {
 		assert!(!episode_list.is_empty(), "Episode list should not be empty");
 	}
 
+	#[tokio::test]
+	async fn test_episodes_stream() {
+		let first: Vec<Episode> = episodes_stream("4", 2)
+			.take(40)
+			.try_collect()
+			.await
+			.unwrap();
+		let numbers: Vec<u32> = first.iter().map(|episode| episode.number.whole()).collect();
+		assert_eq!(numbers, (1..=40).collect::<Vec<_>>());
+	}
+
+	#[tokio::test]
+	async fn test_episodes_range() {
+		let range = episodes_range("4", EpisodeNumber::new(500), EpisodeNumber::new(505))
+			.await
+			.unwrap();
+		let numbers: Vec<u32> = range.iter().map(|episode| episode.number.whole()).collect();
+		assert_eq!(numbers, [500, 501, 502, 503, 504, 505]);
+	}
+
 	#[tokio::test]
 	async fn test_servers() {
 		let episode_list = episodes("4").await.unwrap();
@@ -234,7 +447,9 @@ mod tests {
 			"Can't test servers without episodes"
 		);
 
-		let servers = servers(&episode_list[0].id).await.unwrap();
+		let episode = &episode_list[0].id;
+		let token = episode.token.as_deref().unwrap();
+		let servers = servers(episode, token).await.unwrap();
 		assert_eq!(
 			servers,
 			vec![
@@ -242,11 +457,15 @@ mod tests {
 					name: "HorribleSubs · 1080p HardSub".to_string(),
 					locale: Locale::HardSub,
 					url: "https://kwik.si/e/InzZMv1U52OE".to_string(),
+					id: "animepahe:4:1:HorribleSubs · 1080p HardSub"
+						.parse()
+						.unwrap(),
 				},
 				Server {
 					name: "HorribleSubs · 720p HardSub".to_string(),
 					locale: Locale::HardSub,
 					url: "https://kwik.si/e/wkp5wNBEkkwE".to_string(),
+					id: "animepahe:4:1:HorribleSubs · 720p HardSub".parse().unwrap(),
 				},
 			]
 		);
@@ -261,6 +480,7 @@ mod tests {
 			Source {
 				url: "https://vault-05.padorupado.ru/stream/05/08/0df7ff5cbf5c20bf1834d37b22d918a4faa98d146dd264ce5cb83d3f30fddab6/uwu.m3u8".to_string(),
 				captions: Vec::new(),
+				headers: extractors::referer_headers("https://kwik.si/e/InzZMv1U52OE").unwrap(),
 			}
 		);
 	}
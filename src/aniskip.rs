@@ -1,28 +1,53 @@
-use crate::mal;
+use std::time::Instant;
+
+use crate::{seasons, telemetry, EpisodeId, EpisodeNumber};
 use anyhow::Context as _;
 use reqwest::ClientBuilder;
+use serde::Serialize;
 use serde_json::Value;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct SkipTimes {
 	pub start: f32,
 	pub end: f32,
 	pub skip_type: SkipType,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub enum SkipType {
 	Ed,
 	Op,
 	Recap,
 }
 
+// Numbers past the end of the season map onto the sequel they fall in.
+#[tracing::instrument(err(level = "debug"))]
 pub async fn get_skip_times(
-	title: &str, ep_number: u32, ep_length: f32,
+	title: &str, ep_number: EpisodeNumber, ep_length: f32,
+) -> Result<Vec<SkipTimes>, anyhow::Error> {
+	let (mal_id, ep_number) = seasons::resolve_number(title, ep_number, false).await?;
+	skip_times(&mal_id, ep_number, ep_length).await
+}
+
+// Like `get_skip_times`, but also maps provider numbering that counts from the start of the franchise.
+#[tracing::instrument(skip(episode), fields(%episode), err(level = "debug"))]
+pub async fn get_episode_skip_times(
+	title: &str, episode: &EpisodeId, ep_length: f32,
+) -> Result<Vec<SkipTimes>, anyhow::Error> {
+	let (mal_id, ep_number) = seasons::resolve(title, episode).await?;
+	skip_times(&mal_id, ep_number, ep_length).await
+}
+
+#[tracing::instrument(fields(status = tracing::field::Empty))]
+async fn skip_times(
+	mal_id: &str, ep_number: EpisodeNumber, ep_length: f32,
 ) -> Result<Vec<SkipTimes>, anyhow::Error> {
-	let mal_id = mal::search(title).await?.id;
+	let ep_number = ep_number
+		.mal()
+		.with_context(|| format!("AniSkip has no times for episode {ep_number}"))?;
 	let client = ClientBuilder::new().use_rustls_tls().build()?;
-	let json: Value = client
+	let started = Instant::now();
+	let response = client
 		.get(format!(
 			"https://api.aniskip.com/v2/skip-times/{mal_id}/{ep_number}"
 		))
@@ -35,9 +60,12 @@ pub async fn get_skip_times(
 			("episodeLength", &ep_length.to_string()),
 		])
 		.send()
-		.await?
-		.json()
 		.await?;
+	let url = response.url().to_string();
+	let status = response.status();
+	let text = response.text().await?;
+	telemetry::response(&url, status, started.elapsed(), &text);
+	let json: Value = serde_json::from_str(&text)?;
 
 	let results = json["results"].as_array().context("No results")?;
 	let skip_times: Vec<SkipTimes> = results
@@ -65,7 +93,7 @@ pub async fn get_skip_times(
 
 #[tokio::test]
 async fn test_get_skip_times() {
-	let skip_times = get_skip_times("One Piece", 1, 1500.).await.unwrap();
+	let skip_times = get_skip_times("One Piece", 1.into(), 1500.).await.unwrap();
 	assert_eq!(
 		skip_times,
 		vec![
@@ -0,0 +1,164 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context as _;
+use futures::future::BoxFuture;
+use reqwest::{
+	header::{self, HeaderMap},
+	StatusCode,
+};
+use serde_json::{json, Value};
+use tracing::Instrument as _;
+
+use super::{ChallengeSolver, Clearance};
+use crate::telemetry;
+
+// Whether a response is a Cloudflare interstitial or block page rather than the site.
+pub fn is_challenge(status: StatusCode, headers: &HeaderMap, body: &str) -> bool {
+	if headers
+		.get("cf-mitigated")
+		.is_some_and(|mitigated| mitigated == "challenge")
+	{
+		return true;
+	}
+
+	let server = headers
+		.get(header::SERVER)
+		.and_then(|server| server.to_str().ok())
+		.is_some_and(|server| server.eq_ignore_ascii_case("cloudflare"));
+	let blocked = matches!(
+		status,
+		StatusCode::FORBIDDEN | StatusCode::SERVICE_UNAVAILABLE | StatusCode::TOO_MANY_REQUESTS
+	);
+
+	server
+		&& blocked
+		&& [
+			"<title>Just a moment...</title>",
+			"<title>Attention Required! | Cloudflare</title>",
+			"/cdn-cgi/challenge-platform/",
+		]
+		.iter()
+		.any(|marker| body.contains(marker))
+}
+
+// A FlareSolverr instance, e.g. `FlareSolverr::new("http://localhost:8191")`.
+pub struct FlareSolverr {
+	endpoint: String,
+	client: reqwest::Client,
+}
+
+impl FlareSolverr {
+	pub fn new(endpoint: &str) -> Self {
+		FlareSolverr {
+			endpoint: endpoint.trim_end_matches('/').to_string(),
+			client: reqwest::Client::new(),
+		}
+	}
+}
+
+impl ChallengeSolver for FlareSolverr {
+	fn solve<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Clearance, anyhow::Error>> {
+		let span = tracing::debug_span!("flaresolverr", url = %telemetry::redact(url));
+		Box::pin(
+			async move {
+				let json: Value = self
+					.client
+					.post(format!("{}/v1", self.endpoint))
+					.json(&json!({ "cmd": "request.get", "url": url, "maxTimeout": 60000 }))
+					.send()
+					.await?
+					.json()
+					.await
+					.context("Failed to reach FlareSolverr")?;
+
+				parse_solution(&json)
+			}
+			.instrument(span),
+		)
+	}
+}
+
+fn parse_solution(json: &Value) -> Result<Clearance, anyhow::Error> {
+	anyhow::ensure!(
+		json["status"] == "ok",
+		"FlareSolverr failed: {}",
+		json["message"].as_str().unwrap_or("unknown error")
+	);
+
+	let solution = &json["solution"];
+	let cookies = solution["cookies"].as_array().context("No cookies")?;
+	// The clearance cookie is the one that runs out first, ignoring times too large for a SystemTime.
+	let expires = cookies
+		.iter()
+		.filter_map(|cookie| {
+			let expires = Duration::try_from_secs_f64(cookie["expires"].as_f64()?).ok()?;
+			SystemTime::UNIX_EPOCH.checked_add(expires)
+		})
+		.filter(|expires| *expires > SystemTime::UNIX_EPOCH)
+		.min();
+
+	Ok(Clearance {
+		cookies: cookies
+			.iter()
+			.filter_map(|cookie| {
+				let name = cookie["name"].as_str()?;
+				let value = cookie["value"].as_str()?;
+				Some((name.to_string(), value.to_string()))
+			})
+			.collect(),
+		user_agent: solution["userAgent"].as_str().map(str::to_string),
+		expires,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_is_challenge() {
+		let mut headers = HeaderMap::new();
+		headers.insert(header::SERVER, "cloudflare".parse().unwrap());
+
+		let interstitial = "<html><head><title>Just a moment...</title></head></html>";
+		assert!(is_challenge(StatusCode::FORBIDDEN, &headers, interstitial));
+		assert!(!is_challenge(StatusCode::OK, &headers, "{\"html\":\"\"}"));
+		assert!(!is_challenge(StatusCode::FORBIDDEN, &headers, "Forbidden"));
+
+		headers.insert("cf-mitigated", "challenge".parse().unwrap());
+		assert!(is_challenge(StatusCode::FORBIDDEN, &headers, ""));
+	}
+
+	#[test]
+	fn test_parse_solution() {
+		let json = json!({
+			"status": "ok",
+			"solution": {
+				"userAgent": "Mozilla/5.0",
+				"cookies": [
+					{ "name": "cf_clearance", "value": "abc", "expires": 1700000000.5 },
+					{ "name": "session", "value": "1", "expires": -1 },
+					{ "name": "__cf_bm", "value": "2", "expires": 1e300 },
+				],
+			},
+		});
+
+		let clearance = parse_solution(&json).unwrap();
+		assert_eq!(
+			clearance.cookies,
+			[
+				("cf_clearance".to_string(), "abc".to_string()),
+				("session".to_string(), "1".to_string()),
+				("__cf_bm".to_string(), "2".to_string())
+			]
+		);
+		assert_eq!(clearance.user_agent.as_deref(), Some("Mozilla/5.0"));
+		assert_eq!(
+			clearance.expires,
+			Some(SystemTime::UNIX_EPOCH + Duration::from_secs_f64(1700000000.5))
+		);
+
+		let error = json!({ "status": "error", "message": "Timeout" });
+		assert!(parse_solution(&error).is_err());
+	}
+}
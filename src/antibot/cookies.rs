@@ -0,0 +1,241 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, RwLock},
+	time::{Duration, SystemTime},
+};
+
+use reqwest::{
+	header::{self, HeaderMap},
+	Url,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+struct Cookie {
+	name: String,
+	value: String,
+	// None for session cookies, which last as long as the process.
+	expires: Option<SystemTime>,
+}
+
+// Cookies by domain, shared between clients and dropped once they expire.
+#[derive(Clone, Default)]
+pub struct CookieJar {
+	domains: Arc<RwLock<HashMap<String, Vec<Cookie>>>>,
+}
+
+impl CookieJar {
+	pub fn new() -> Self {
+		CookieJar::default()
+	}
+
+	pub fn set(&self, domain: &str, name: &str, value: &str, expires: Option<SystemTime>) {
+		let mut domains = self.domains.write().unwrap();
+		let cookies = domains
+			.entry(domain.trim_start_matches('.').to_lowercase())
+			.or_default();
+		cookies.retain(|cookie| cookie.name != name);
+		cookies.push(Cookie {
+			name: name.to_string(),
+			value: value.to_string(),
+			expires,
+		});
+	}
+
+	// Stores every `Set-Cookie` of a response from `url`.
+	pub fn store(&self, url: &Url, headers: &HeaderMap) {
+		let Some(host) = url.host_str() else {
+			return;
+		};
+
+		let now = SystemTime::now();
+		for value in headers.get_all(header::SET_COOKIE) {
+			let Some(set) = value.to_str().ok().and_then(|value| parse(value, now)) else {
+				continue;
+			};
+			let domain = set
+				.domain
+				.as_deref()
+				.filter(|domain| domain_matches(host, domain))
+				.unwrap_or(host);
+			self.set(domain, &set.name, &set.value, set.expires);
+		}
+	}
+
+	// The `Cookie` header for a request to `url`, or None without any live cookies.
+	pub fn header(&self, url: &Url) -> Option<String> {
+		let host = url.host_str()?.to_lowercase();
+		let now = SystemTime::now();
+
+		let domains = self.domains.read().unwrap();
+		let cookies: Vec<String> = domains
+			.iter()
+			.filter(|(domain, _)| host == **domain || host.ends_with(&format!(".{domain}")))
+			.flat_map(|(_, cookies)| cookies)
+			.filter(|cookie| cookie.expires.is_none_or(|expires| expires > now))
+			.map(|cookie| format!("{}={}", cookie.name, cookie.value))
+			.collect();
+
+		(!cookies.is_empty()).then(|| cookies.join("; "))
+	}
+
+	pub fn get(&self, url: &Url, name: &str) -> Option<String> {
+		let header = self.header(url)?;
+		header
+			.split("; ")
+			.filter_map(|cookie| cookie.split_once('='))
+			.find(|(key, _)| *key == name)
+			.map(|(_, value)| value.to_string())
+	}
+
+	pub fn contains(&self, url: &Url, name: &str) -> bool {
+		self.get(url, name).is_some()
+	}
+
+	// Forgets the cookies of `url`'s host, e.g. once a site stops accepting them.
+	pub fn clear(&self, url: &Url) {
+		if let Some(host) = url.host_str() {
+			let host = host.to_lowercase();
+			self.domains
+				.write()
+				.unwrap()
+				.retain(|domain, _| host != *domain && !host.ends_with(&format!(".{domain}")));
+		}
+	}
+}
+
+// A response may only set cookies for its own host or a domain above it, like a browser allows.
+// Single-label domains like `ru` are top-level, so they'd reach every site under them.
+fn domain_matches(host: &str, domain: &str) -> bool {
+	let host = host.to_lowercase();
+	let domain = domain.trim_start_matches('.').to_lowercase();
+	host == domain || (domain.contains('.') && host.ends_with(&format!(".{domain}")))
+}
+
+struct SetCookie {
+	name: String,
+	value: String,
+	domain: Option<String>,
+	expires: Option<SystemTime>,
+}
+
+// Max-Age wins over Expires, as in browsers.
+fn parse(set_cookie: &str, now: SystemTime) -> Option<SetCookie> {
+	let mut parts = set_cookie.split(';');
+	let (name, value) = parts.next()?.trim().split_once('=')?;
+	if name.is_empty() {
+		return None;
+	}
+
+	let mut set = SetCookie {
+		name: name.to_string(),
+		value: value.trim_matches('"').to_string(),
+		domain: None,
+		expires: None,
+	};
+	let mut max_age = None;
+	for attribute in parts {
+		let (key, value) = attribute
+			.trim()
+			.split_once('=')
+			.unwrap_or((attribute.trim(), ""));
+		match key.to_ascii_lowercase().as_str() {
+			"domain" if !value.is_empty() => set.domain = Some(value.to_string()),
+			"expires" => set.expires = httpdate::parse_http_date(value).ok(),
+			"max-age" => max_age = value.parse::<i64>().ok(),
+			_ => (),
+		}
+	}
+
+	if let Some(seconds) = max_age {
+		let seconds = u64::try_from(seconds).unwrap_or(0);
+		set.expires = Some(now + Duration::from_secs(seconds));
+	}
+	Some(set)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse() {
+		let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+		let set = parse("__ddg1_=abc; Domain=.animepahe.ru; Max-Age=60; Path=/", now).unwrap();
+		assert_eq!((set.name.as_str(), set.value.as_str()), ("__ddg1_", "abc"));
+		assert_eq!(set.domain.as_deref(), Some(".animepahe.ru"));
+		assert_eq!(set.expires, Some(now + Duration::from_secs(60)));
+
+		let set = parse("id=1; Expires=Thu, 01 Jan 1970 00:00:10 GMT", now).unwrap();
+		assert_eq!(
+			set.expires,
+			Some(SystemTime::UNIX_EPOCH + Duration::from_secs(10))
+		);
+		assert_eq!(parse("id=1; Max-Age=-1", now).unwrap().expires, Some(now));
+		assert!(parse("no-value", now).is_none());
+	}
+
+	#[test]
+	fn test_jar() {
+		let jar = CookieJar::new();
+		let url = Url::parse("https://www.animepahe.ru/api").unwrap();
+		let other = Url::parse("https://kwik.si/e/1").unwrap();
+
+		let mut headers = HeaderMap::new();
+		headers.append(
+			header::SET_COOKIE,
+			"__ddg1_=a; Domain=animepahe.ru".parse().unwrap(),
+		);
+		headers.append(header::SET_COOKIE, "__ddg5_=b; Max-Age=0".parse().unwrap());
+		jar.store(&url, &headers);
+		jar.set("www.animepahe.ru", "__ddg2_", "c", None);
+
+		let header = jar.header(&url).unwrap();
+		assert!(header.contains("__ddg1_=a") && header.contains("__ddg2_=c"));
+		assert!(!header.contains("__ddg5_"));
+		assert!(jar.contains(&url, "__ddg2_"));
+		assert_eq!(jar.header(&other), None);
+
+		jar.clear(&url);
+		assert_eq!(jar.header(&url), None);
+	}
+
+	#[test]
+	fn test_store_foreign_domain() {
+		let jar = CookieJar::new();
+		let url = Url::parse("https://kwik.si/e/1").unwrap();
+
+		let mut headers = HeaderMap::new();
+		headers.append(
+			header::SET_COOKIE,
+			"session=evil; Domain=animepahe.ru".parse().unwrap(),
+		);
+		jar.store(&url, &headers);
+
+		let pahe = Url::parse("https://animepahe.ru/api").unwrap();
+		assert_eq!(jar.header(&pahe), None);
+		assert_eq!(jar.get(&url, "session").as_deref(), Some("evil"));
+	}
+
+	#[test]
+	fn test_store_top_level_domain() {
+		let jar = CookieJar::new();
+		let url = Url::parse("https://x.animepahe.ru/").unwrap();
+
+		let mut headers = HeaderMap::new();
+		headers.append(header::SET_COOKIE, "a=1; Domain=ru".parse().unwrap());
+		headers.append(
+			header::SET_COOKIE,
+			"b=2; Domain=.animepahe.ru".parse().unwrap(),
+		);
+		jar.store(&url, &headers);
+
+		let other = Url::parse("https://kwik.ru/").unwrap();
+		assert_eq!(jar.header(&other), None);
+		assert_eq!(jar.get(&url, "a").as_deref(), Some("1"));
+		let pahe = Url::parse("https://animepahe.ru/").unwrap();
+		assert_eq!(jar.header(&pahe).as_deref(), Some("b=2"));
+		assert!(!domain_matches("x.animepahe.ru", "ru"));
+		assert!(domain_matches("localhost", "localhost"));
+	}
+}
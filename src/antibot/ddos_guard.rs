@@ -0,0 +1,240 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::Instant,
+};
+
+use anyhow::Context as _;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::{
+	header::{self, HeaderMap},
+	StatusCode, Url,
+};
+use serde::de::DeserializeOwned;
+
+use super::{cookies, Blocked, Page, Protection};
+use crate::telemetry;
+
+const CHECK: &str = "https://check.ddos-guard.net/check.js";
+const USER_AGENT: &str =
+	"Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36";
+
+// Trimmed down version of what the challenge script reports, which is all the mark endpoint checks for.
+const FINGERPRINT: &str = r#"{"_geo":true,"_sensor":{"gyroscope":false,"accelerometer":false,"magnetometer":false,"absorient":false,"relorient":false},"userAgent":"Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36","webdriver":false,"language":"en-US","colorDepth":24,"deviceMemory":8,"pixelRatio":1,"hardwareConcurrency":8,"screenResolution":[1920,1080],"availableScreenResolution":[1920,1040],"timezoneOffset":0,"timezone":"UTC","sessionStorage":true,"localStorage":true,"indexedDb":true,"addBehavior":false,"openDatabase":false,"cpuClass":"not available","platform":"Win32","plugins":[],"canvas":[],"webgl":false,"webglVendorAndRenderer":"","adBlock":false,"hasLiedLanguages":false,"hasLiedResolution":false,"hasLiedOs":false,"hasLiedBrowser":false,"touchSupport":[0,false,false],"fonts":[],"audio":"100.00000000000000"}"#;
+
+lazy_static! {
+	// One handshake per host at a time, so a burst of cold requests waits on the first one's cookies.
+	static ref HANDSHAKES: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>> = Mutex::default();
+}
+
+// Whether a response is DDoS-Guard's block or JS challenge page rather than the site.
+pub fn is_challenge(status: StatusCode, headers: &HeaderMap, body: &str) -> bool {
+	let server = headers
+		.get(header::SERVER)
+		.and_then(|server| server.to_str().ok())
+		.is_some_and(|server| server.to_ascii_lowercase().contains("ddos-guard"));
+	let blocked = matches!(
+		status,
+		StatusCode::FORBIDDEN | StatusCode::SERVICE_UNAVAILABLE
+	);
+
+	(server && blocked)
+		|| body.contains("<title>DDoS-Guard</title>")
+		|| body.contains("/.well-known/ddos-guard/js-challenge")
+}
+
+// A client for a site behind DDoS-Guard that handshakes on first use and again whenever it gets challenged.
+#[derive(Clone)]
+pub struct Client {
+	client: reqwest::Client,
+	// The site's domains, the first of which is the referer for requests elsewhere, like its embeds.
+	origins: Vec<Url>,
+}
+
+impl Client {
+	pub fn new(origins: &[String]) -> Result<Self, anyhow::Error> {
+		let origins = origins
+			.iter()
+			.map(|origin| Url::parse(origin))
+			.collect::<Result<Vec<_>, _>>()?;
+		anyhow::ensure!(!origins.is_empty(), "No origins");
+
+		let client = reqwest::Client::builder().user_agent(USER_AGENT).build()?;
+		Ok(Client { client, origins })
+	}
+
+	pub async fn text(&self, url: &str) -> Result<String, anyhow::Error> {
+		let page = self.get(url).await?;
+		anyhow::ensure!(page.status.is_success(), "{url} returned {}", page.status);
+		Ok(page.body)
+	}
+
+	pub async fn json<T: DeserializeOwned>(&self, url: &str) -> Result<T, anyhow::Error> {
+		let text = self.text(url).await?;
+		serde_json::from_str(&text).with_context(|| format!("Failed to parse {url}"))
+	}
+
+	#[tracing::instrument(
+		skip_all,
+		fields(url = %telemetry::redact(url), status = tracing::field::Empty, retries = tracing::field::Empty)
+	)]
+	pub async fn get(&self, url: &str) -> Result<Page, anyhow::Error> {
+		let url = Url::parse(url)?;
+		// Other hosts, like embeds, only get a handshake once they turn out to be protected.
+		let own = self
+			.origins
+			.iter()
+			.find(|origin| origin.host() == url.host());
+		if own.is_some() && !cookies().contains(&url, "__ddg2_") {
+			let lock = handshake_lock(&url);
+			let _guard = lock.lock().await;
+			if !cookies().contains(&url, "__ddg2_") {
+				self.handshake(&url).await?;
+			}
+		}
+		let referer = own.unwrap_or(&self.origins[0]).to_string();
+
+		let mut refreshed = false;
+		loop {
+			let request = self
+				.client
+				.get(url.clone())
+				.header(header::REFERER, &referer);
+			let sent = cookies().get(&url, "__ddg2_");
+			let started = Instant::now();
+			let response = with_cookies(request, &url).send().await?;
+			cookies().store(&url, response.headers());
+			let status = response.status();
+			let headers = response.headers().clone();
+			let body = response.text().await?;
+			telemetry::response(url.as_str(), status, started.elapsed(), &body);
+
+			// Expired cookies on the site itself come back as a plain 403.
+			let challenged = is_challenge(status, &headers, &body)
+				|| (own.is_some() && status == StatusCode::FORBIDDEN);
+			if challenged {
+				if refreshed {
+					tracing::warn!("blocked by DDoS-Guard");
+					return Err(Blocked {
+						protection: Protection::DdosGuard,
+						url: url.to_string(),
+					}
+					.into());
+				}
+				refreshed = true;
+				tracing::Span::current().record("retries", 1);
+				let lock = handshake_lock(&url);
+				let _guard = lock.lock().await;
+				// Whoever held the lock may already have replaced the cookies this request was rejected with.
+				if cookies().get(&url, "__ddg2_") == sent {
+					cookies().clear(&url);
+					self.handshake(&url).await?;
+				}
+				continue;
+			}
+
+			return Ok(Page { status, body });
+		}
+	}
+
+	// check.js hands out the `__ddg2_` cookie and the id path, which sets `__ddg1_`,
+	// and the mark request then sets the cookies the JS challenge would have.
+	#[tracing::instrument(skip_all, fields(host = url.host_str()))]
+	async fn handshake(&self, url: &Url) -> Result<(), anyhow::Error> {
+		let host = url.host_str().context("No host")?;
+		let origin = url.origin().ascii_serialization();
+
+		let response = self.client.get(CHECK).send().await?;
+		let etag = response
+			.headers()
+			.get(header::ETAG)
+			.context("ETAG not found")?
+			.to_str()?
+			.to_string();
+		cookies().set(host, "__ddg2_", &etag, None);
+		let script = response.text().await?;
+
+		if let Some(path) = id_path(&script) {
+			let id = Url::parse(&format!("{origin}{path}"))?;
+			let response = with_cookies(self.client.get(id.clone()), &id)
+				.send()
+				.await?;
+			cookies().store(&id, response.headers());
+			tracing::debug!(status = response.status().as_u16(), "id");
+		}
+
+		// Sites without the JS challenge reject the mark, which is fine.
+		let mark = Url::parse(&format!("{origin}/.well-known/ddos-guard/mark/"))?;
+		let request = self
+			.client
+			.post(mark.clone())
+			.header(header::CONTENT_TYPE, "text/plain;charset=UTF-8")
+			.body(FINGERPRINT);
+		if let Ok(response) = with_cookies(request, &mark).send().await {
+			cookies().store(&mark, response.headers());
+			tracing::debug!(status = response.status().as_u16(), "mark");
+		}
+
+		Ok(())
+	}
+}
+
+fn handshake_lock(url: &Url) -> Arc<tokio::sync::Mutex<()>> {
+	let host = url.host_str().unwrap_or_default().to_string();
+	HANDSHAKES.lock().unwrap().entry(host).or_default().clone()
+}
+
+fn with_cookies(request: reqwest::RequestBuilder, url: &Url) -> reqwest::RequestBuilder {
+	match cookies().header(url) {
+		Some(cookies) => request.header(header::COOKIE, cookies),
+		None => request,
+	}
+}
+
+// check.js loads `new Image().src = '/.well-known/ddos-guard/id/...'`.
+fn id_path(script: &str) -> Option<&str> {
+	lazy_static! {
+		static ref ID: Regex =
+			Regex::new(r#"['"](/\.well-known/ddos-guard/id/[^'"]+)['"]"#).unwrap();
+	}
+
+	ID.captures(script)
+		.and_then(|captures| captures.get(1))
+		.map(|path| path.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_id_path() {
+		let script = "(function(){new Image().src = '/.well-known/ddos-guard/id/ab12CD34';})();";
+		assert_eq!(id_path(script), Some("/.well-known/ddos-guard/id/ab12CD34"));
+		assert_eq!(id_path("console.log(1)"), None);
+	}
+
+	#[test]
+	fn test_is_challenge() {
+		let mut headers = HeaderMap::new();
+		headers.insert(header::SERVER, "ddos-guard".parse().unwrap());
+
+		assert!(is_challenge(StatusCode::FORBIDDEN, &headers, ""));
+		assert!(!is_challenge(StatusCode::OK, &headers, "{\"data\":[]}"));
+		assert!(is_challenge(
+			StatusCode::OK,
+			&HeaderMap::new(),
+			"<html><head><title>DDoS-Guard</title></head></html>"
+		));
+		assert!(!is_challenge(StatusCode::NOT_FOUND, &HeaderMap::new(), ""));
+	}
+
+	#[tokio::test]
+	async fn test_handshake() {
+		let client = Client::new(&["https://animepahe.ru".to_string()]).unwrap();
+		let html = client.text("https://animepahe.ru/a/4").await.unwrap();
+		assert!(!html.is_empty(), "Page should not be empty");
+		assert!(cookies().contains(&Url::parse("https://animepahe.ru/").unwrap(), "__ddg2_"));
+	}
+}
@@ -0,0 +1,173 @@
+pub mod cloudflare;
+mod cookies;
+pub mod ddos_guard;
+
+use std::{
+	collections::HashMap,
+	fmt,
+	sync::{Arc, Mutex, RwLock},
+	time::{Instant, SystemTime},
+};
+
+use anyhow::Context as _;
+pub use cookies::CookieJar;
+use futures::future::BoxFuture;
+use lazy_static::lazy_static;
+use reqwest::{header, StatusCode, Url};
+use serde::de::DeserializeOwned;
+
+use crate::telemetry;
+
+lazy_static! {
+	// Shared by every request, so cookies earned once serve all the others.
+	static ref JAR: CookieJar = CookieJar::new();
+	static ref CLIENT: reqwest::Client = reqwest::Client::new();
+	static ref SOLVER: RwLock<Option<Arc<dyn ChallengeSolver>>> = RwLock::new(None);
+	// Clearance cookies only work with the user agent that earned them.
+	static ref USER_AGENTS: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+	// One solve per host at a time, so a burst of challenged requests shares the first one's clearance.
+	static ref SOLVES: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>> = Mutex::default();
+}
+
+pub fn cookies() -> &'static CookieJar {
+	&JAR
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protection {
+	Cloudflare,
+	DdosGuard,
+}
+
+impl fmt::Display for Protection {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Protection::Cloudflare => write!(f, "Cloudflare"),
+			Protection::DdosGuard => write!(f, "DDoS-Guard"),
+		}
+	}
+}
+
+// Returned, inside `anyhow::Error`, when a site answers with a challenge that couldn't be cleared.
+#[derive(Debug, thiserror::Error)]
+#[error("blocked by {protection} at {url}")]
+pub struct Blocked {
+	pub protection: Protection,
+	pub url: String,
+}
+
+// What a solver got past a challenge with, for protozoa to send along with its own requests.
+#[derive(Clone, Debug, Default)]
+pub struct Clearance {
+	pub cookies: Vec<(String, String)>,
+	pub user_agent: Option<String>,
+	// When the cookies stop working, if the solver knows.
+	pub expires: Option<SystemTime>,
+}
+
+// Clears challenges protozoa can't, e.g. with a headless browser. See `cloudflare::FlareSolverr`.
+pub trait ChallengeSolver: Send + Sync {
+	fn solve<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Clearance, anyhow::Error>>;
+}
+
+pub fn set_solver(solver: impl ChallengeSolver + 'static) {
+	*SOLVER.write().unwrap() = Some(Arc::new(solver));
+}
+
+// A response body along with its status, which isn't checked so callers can tell failures apart.
+#[derive(Debug)]
+pub struct Page {
+	pub status: StatusCode,
+	pub body: String,
+}
+
+pub async fn text(url: &str) -> Result<String, anyhow::Error> {
+	Ok(get(url).await?.body)
+}
+
+pub async fn json<T: DeserializeOwned>(url: &str) -> Result<T, anyhow::Error> {
+	let text = text(url).await?;
+	serde_json::from_str(&text).with_context(|| format!("Failed to parse {url}"))
+}
+
+// GETs a page with the cookies in the jar, clearing Cloudflare challenges with the solver when one is set.
+#[tracing::instrument(
+	skip_all,
+	fields(url = %telemetry::redact(url), status = tracing::field::Empty, retries = tracing::field::Empty)
+)]
+pub async fn get(url: &str) -> Result<Page, anyhow::Error> {
+	let url = Url::parse(url)?;
+	let mut solved = false;
+	loop {
+		let mut request = CLIENT.get(url.clone());
+		if let Some(cookies) = JAR.header(&url) {
+			request = request.header(header::COOKIE, cookies);
+		}
+		if let Some(user_agent) = user_agent(&url) {
+			request = request.header(header::USER_AGENT, user_agent);
+		}
+
+		let sent = JAR.get(&url, "cf_clearance");
+		let started = Instant::now();
+		let response = request.send().await?;
+		JAR.store(&url, response.headers());
+		let status = response.status();
+		let headers = response.headers().clone();
+		let body = response.text().await?;
+		telemetry::response(url.as_str(), status, started.elapsed(), &body);
+
+		if !cloudflare::is_challenge(status, &headers, &body) {
+			return Ok(Page { status, body });
+		}
+
+		let solver = SOLVER.read().unwrap().clone();
+		match solver {
+			Some(solver) if !solved => {
+				tracing::Span::current().record("retries", 1);
+				let lock = solve_lock(&url);
+				let _guard = lock.lock().await;
+				// Whoever held the lock may already have earned a clearance this request didn't have.
+				if JAR.get(&url, "cf_clearance") == sent {
+					tracing::debug!("solving Cloudflare challenge");
+					let clearance = solver.solve(url.as_str()).await?;
+					store(&url, clearance);
+				}
+				solved = true;
+			}
+			_ => {
+				tracing::warn!("blocked by Cloudflare");
+				return Err(Blocked {
+					protection: Protection::Cloudflare,
+					url: url.to_string(),
+				}
+				.into());
+			}
+		}
+	}
+}
+
+fn solve_lock(url: &Url) -> Arc<tokio::sync::Mutex<()>> {
+	let host = url.host_str().unwrap_or_default().to_string();
+	SOLVES.lock().unwrap().entry(host).or_default().clone()
+}
+
+fn user_agent(url: &Url) -> Option<String> {
+	let host = url.host_str()?;
+	USER_AGENTS.read().unwrap().get(host).cloned()
+}
+
+fn store(url: &Url, clearance: Clearance) {
+	let Some(host) = url.host_str() else {
+		return;
+	};
+
+	for (name, value) in &clearance.cookies {
+		JAR.set(host, name, value, clearance.expires);
+	}
+	if let Some(user_agent) = clearance.user_agent {
+		USER_AGENTS
+			.write()
+			.unwrap()
+			.insert(host.to_string(), user_agent);
+	}
+}
@@ -0,0 +1,461 @@
+mod openapi;
+
+use std::{
+	env,
+	future::Future,
+	net::{IpAddr, SocketAddr},
+	num::NonZeroU32,
+	str::FromStr,
+	sync::Arc,
+	time::Duration,
+};
+
+use axum::{
+	extract::{ConnectInfo, Path, Query, Request, State},
+	http::{HeaderMap, StatusCode},
+	middleware::{self, Next},
+	response::{IntoResponse, Redirect, Response},
+	routing::get,
+	Json, Router,
+};
+use clap::Parser;
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use moka::future::Cache;
+use protozoa::{
+	aniskip,
+	antibot::{self, cloudflare::FlareSolverr, Blocked},
+	diagnose, mirrors,
+	proxy::Proxy,
+	telemetry, AnimeId, EpisodeId, EpisodeNumber, Provider, ServerId, Source,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser)]
+#[command(name = "protozoa-server", version, about = "Serve protozoa over HTTP")]
+struct Args {
+	/// Address to listen on
+	#[arg(long, default_value = "127.0.0.1:3000")]
+	bind: SocketAddr,
+	/// Requests per minute allowed for each client
+	#[arg(long, default_value_t = NonZeroU32::new(60).unwrap())]
+	rate_limit: NonZeroU32,
+	/// Seconds to keep responses cached
+	#[arg(long, default_value_t = 600)]
+	cache_ttl: u64,
+	/// Rate limit by the last X-Forwarded-For address, the one the gateway in front appended
+	#[arg(long)]
+	trust_forwarded: bool,
+	/// Title used by /health/providers to run every provider end-to-end
+	#[arg(long, default_value = "One Piece")]
+	canary: String,
+	/// Address clients reach the server at, used for proxied source urls (defaults to http://<bind>)
+	#[arg(long)]
+	public_url: Option<String>,
+	/// FlareSolverr endpoint used to get past Cloudflare challenges, e.g. http://localhost:8191
+	#[arg(long)]
+	flaresolverr: Option<String>,
+	/// Mirrors to try in order for a site, e.g. hianime=https://hianime.sx,https://hianime.to (repeatable)
+	#[arg(long)]
+	mirror: Vec<String>,
+	/// Also log raw response bodies at trace level, to debug selectors that stopped matching
+	#[arg(long)]
+	dump_bodies: bool,
+}
+
+// Upper bounds in seconds for request and key derivation latencies, from cached pages to slow embeds.
+const BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10., 30.];
+
+#[derive(Clone)]
+struct AppState {
+	cache: Cache<String, Value>,
+	limiter: Arc<DefaultKeyedRateLimiter<IpAddr>>,
+	trust_forwarded: bool,
+	canary: Arc<str>,
+	proxy: Proxy,
+	metrics: PrometheusHandle,
+}
+
+enum ApiError {
+	BadRequest(String),
+	TooManyRequests,
+	Blocked(anyhow::Error),
+	Upstream(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ApiError {
+	fn from(error: anyhow::Error) -> Self {
+		match error.is::<Blocked>() {
+			true => ApiError::Blocked(error),
+			false => ApiError::Upstream(error),
+		}
+	}
+}
+
+// A failed fetch shared by concurrent requests, which each answer with its message.
+impl From<Arc<anyhow::Error>> for ApiError {
+	fn from(error: Arc<anyhow::Error>) -> Self {
+		let message = anyhow::anyhow!("{error:#}");
+		match error.is::<Blocked>() {
+			true => ApiError::Blocked(message),
+			false => ApiError::Upstream(message),
+		}
+	}
+}
+
+impl IntoResponse for ApiError {
+	fn into_response(self) -> Response {
+		let (status, message) = match self {
+			ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+			ApiError::TooManyRequests => (
+				StatusCode::TOO_MANY_REQUESTS,
+				"Too many requests".to_string(),
+			),
+			ApiError::Blocked(error) => (StatusCode::SERVICE_UNAVAILABLE, format!("{error:#}")),
+			ApiError::Upstream(error) => (StatusCode::BAD_GATEWAY, format!("{error:#}")),
+		};
+		(status, Json(json!({ "error": message }))).into_response()
+	}
+}
+
+// Logs to stderr at info by default, RUST_LOG replaces the filter, e.g. `RUST_LOG=protozoa=debug`.
+fn init_tracing(dump_bodies: bool) {
+	let body = match dump_bodies {
+		true => "trace",
+		false => "off",
+	};
+	let directives = match env::var(EnvFilter::DEFAULT_ENV) {
+		Ok(directives) if dump_bodies => format!("{directives},{}=trace", telemetry::BODY_TARGET),
+		Ok(directives) => directives,
+		Err(_) => format!("warn,protozoa=info,{}={body}", telemetry::BODY_TARGET),
+	};
+	tracing_subscriber::fmt()
+		.with_env_filter(EnvFilter::new(directives))
+		.with_writer(std::io::stderr)
+		.init();
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+	let args = Args::parse();
+	init_tracing(args.dump_bodies);
+	let public_url = args
+		.public_url
+		.unwrap_or_else(|| format!("http://{}", args.bind));
+	if let Some(endpoint) = &args.flaresolverr {
+		antibot::set_solver(FlareSolverr::new(endpoint));
+	}
+	for mirror in &args.mirror {
+		mirrors::set_from_str(mirror)?;
+	}
+
+	let state = AppState {
+		cache: Cache::builder()
+			.max_capacity(10_000)
+			.time_to_live(Duration::from_secs(args.cache_ttl))
+			.build(),
+		limiter: Arc::new(RateLimiter::keyed(Quota::per_minute(args.rate_limit))),
+		trust_forwarded: args.trust_forwarded,
+		canary: args.canary.into(),
+		proxy: Proxy::new(&format!("{}/proxy/", public_url.trim_end_matches('/')))?,
+		metrics: PrometheusBuilder::new()
+			.set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), BUCKETS)?
+			.install_recorder()?,
+	};
+
+	// Forget idle clients so the limiter doesn't grow with every address ever seen.
+	let limiter = state.limiter.clone();
+	tokio::spawn(async move {
+		let mut interval = tokio::time::interval(Duration::from_secs(60));
+		loop {
+			interval.tick().await;
+			limiter.retain_recent();
+		}
+	});
+
+	let listener = tokio::net::TcpListener::bind(args.bind).await?;
+	eprintln!("Listening on http://{}", listener.local_addr()?);
+	axum::serve(
+		listener,
+		app(state).into_make_service_with_connect_info::<SocketAddr>(),
+	)
+	.with_graceful_shutdown(async {
+		let _ = tokio::signal::ctrl_c().await;
+	})
+	.await?;
+
+	Ok(())
+}
+
+fn app(state: AppState) -> Router {
+	let api = Router::new()
+		.route("/search", get(search))
+		.route("/episodes/{id}", get(episodes))
+		.route("/episodes/{provider}/{id}", get(legacy_episodes))
+		.route("/related/{id}", get(related))
+		.route("/servers/{id}", get(servers))
+		.route("/servers/{provider}/{ep_id}", get(legacy_servers))
+		.route("/source", get(source))
+		.route("/skip", get(skip))
+		.nest("/proxy", state.proxy.router())
+		.route_layer(middleware::from_fn_with_state(state.clone(), rate_limit));
+
+	Router::new()
+		.merge(api)
+		.route("/health", get(health))
+		.route("/health/providers", get(health_providers))
+		.route("/health/providers/{provider}", get(health_provider))
+		.route("/metrics", get(metrics))
+		.route("/openapi.json", get(|| async { Json(openapi::document()) }))
+		.with_state(state)
+}
+
+async fn rate_limit(
+	State(state): State<AppState>, ConnectInfo(addr): ConnectInfo<SocketAddr>, request: Request,
+	next: Next,
+) -> Response {
+	let ip = client_ip(request.headers(), addr, state.trust_forwarded);
+	match state.limiter.check_key(&ip) {
+		Ok(()) => next.run(request).await,
+		Err(_) => ApiError::TooManyRequests.into_response(),
+	}
+}
+
+// Earlier X-Forwarded-For entries come from the client, so only the gateway's own entry is trusted.
+fn client_ip(headers: &HeaderMap, addr: SocketAddr, trust_forwarded: bool) -> IpAddr {
+	let forwarded = headers
+		.get("x-forwarded-for")
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.rsplit(',').next())
+		.and_then(|ip| ip.trim().parse().ok());
+
+	match forwarded {
+		Some(ip) if trust_forwarded => ip,
+		_ => addr.ip(),
+	}
+}
+
+fn provider(name: &str) -> Result<Provider, ApiError> {
+	Provider::from(name).ok_or_else(|| ApiError::BadRequest(format!("Unknown provider {name}")))
+}
+
+fn parse<T: FromStr<Err = anyhow::Error>>(id: &str) -> Result<T, ApiError> {
+	id.parse()
+		.map_err(|e: anyhow::Error| ApiError::BadRequest(e.to_string()))
+}
+
+async fn cached<T, F>(state: &AppState, key: String, fetch: F) -> Result<Json<Value>, ApiError>
+where
+	T: Serialize,
+	F: Future<Output = Result<T, anyhow::Error>>,
+{
+	// Concurrent misses for one key wait on a single fetch rather than each going upstream.
+	let value = state
+		.cache
+		.try_get_with(key, async {
+			let value = fetch.await?;
+			Ok::<_, anyhow::Error>(serde_json::to_value(value)?)
+		})
+		.await?;
+	Ok(Json(value))
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+	provider: String,
+	query: String,
+}
+
+async fn search(
+	State(state): State<AppState>, Query(params): Query<SearchParams>,
+) -> Result<Json<Value>, ApiError> {
+	let provider = provider(&params.provider)?;
+	let key = format!("search/{provider}/{}", params.query);
+	cached(&state, key, protozoa::search(&provider, &params.query)).await
+}
+
+async fn episodes(
+	State(state): State<AppState>, Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+	let id: AnimeId = parse(&id)?;
+	cached(&state, format!("episodes/{id}"), protozoa::episodes(&id)).await
+}
+
+async fn related(
+	State(state): State<AppState>, Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+	let id: AnimeId = parse(&id)?;
+	cached(&state, format!("related/{id}"), protozoa::related(&id)).await
+}
+
+async fn servers(
+	State(state): State<AppState>, Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+	let id: EpisodeId = parse(&id)?;
+	cached(&state, format!("servers/{id}"), protozoa::servers(&id)).await
+}
+
+// The routes from before ids carried their provider, redirected to the ones above.
+async fn legacy_episodes(Path((name, id)): Path<(String, String)>) -> Result<Redirect, ApiError> {
+	let id: AnimeId = parse(&format!("{name}:{id}"))?;
+	Ok(Redirect::permanent(&format!("/episodes/{id}")))
+}
+
+// `ep_id` is an episode id from /episodes, either whole or without its provider.
+async fn legacy_servers(Path((name, ep_id)): Path<(String, String)>) -> Result<Redirect, ApiError> {
+	let provider = provider(&name)?;
+	let id = match ep_id.parse::<EpisodeId>() {
+		Ok(id) => id,
+		Err(_) => parse(&format!("{name}:{ep_id}"))?,
+	};
+	if id.provider() != provider {
+		let message = format!("{id} isn't a {provider} episode");
+		return Err(ApiError::BadRequest(message));
+	}
+	Ok(Redirect::permanent(&format!("/servers/{id}")))
+}
+
+// Either a server id from /servers, or a provider and a server url.
+#[derive(Deserialize)]
+struct SourceParams {
+	id: Option<String>,
+	provider: Option<String>,
+	url: Option<String>,
+	// Rewrites the source to go through `/proxy`, for players that can't send the upstream headers.
+	#[serde(default)]
+	proxy: bool,
+}
+
+async fn source(
+	State(state): State<AppState>, Query(params): Query<SourceParams>,
+) -> Result<Json<Value>, ApiError> {
+	let Json(value) = match (&params.id, &params.provider, &params.url) {
+		(Some(id), _, _) => {
+			let id: ServerId = parse(id)?;
+			cached(&state, format!("source/{id}"), protozoa::source(&id)).await?
+		}
+		(None, Some(name), Some(url)) => {
+			let provider = provider(name)?;
+			let key = format!("source/{provider}/{url}");
+			cached(&state, key, protozoa::get_source(&provider, url)).await?
+		}
+		_ => {
+			let message = "Expected id, or provider and url".to_string();
+			return Err(ApiError::BadRequest(message));
+		}
+	};
+	if !params.proxy {
+		return Ok(Json(value));
+	}
+
+	let source: Source = serde_json::from_value(value).map_err(anyhow::Error::from)?;
+	let proxied = state.proxy.register(&source)?;
+	Ok(Json(
+		serde_json::to_value(proxied).map_err(anyhow::Error::from)?,
+	))
+}
+
+#[derive(Deserialize)]
+struct SkipParams {
+	title: String,
+	episode: EpisodeNumber,
+	length: f32,
+}
+
+async fn skip(
+	State(state): State<AppState>, Query(params): Query<SkipParams>,
+) -> Result<Json<Value>, ApiError> {
+	let key = format!("skip/{}/{}/{}", params.title, params.episode, params.length);
+	let fetch = aniskip::get_skip_times(&params.title, params.episode, params.length);
+	cached(&state, key, fetch).await
+}
+
+async fn metrics(State(state): State<AppState>) -> String {
+	state.metrics.render()
+}
+
+async fn health() -> Json<Value> {
+	Json(json!({ "status": "ok" }))
+}
+
+// Runs every provider end-to-end; reports are cached like any other response since each run hits four sites.
+async fn health_providers(State(state): State<AppState>) -> Result<Response, ApiError> {
+	let key = "health/providers".to_string();
+	let Json(reports) = cached(&state, key, async {
+		Ok::<_, anyhow::Error>(diagnose::diagnose_all(&state.canary).await)
+	})
+	.await?;
+	Ok(health_response(reports))
+}
+
+async fn health_provider(
+	State(state): State<AppState>, Path(name): Path<String>,
+) -> Result<Response, ApiError> {
+	let provider = provider(&name)?;
+	let key = format!("health/providers/{provider}");
+	let Json(reports) = cached(&state, key, async {
+		Ok::<_, anyhow::Error>(vec![diagnose::diagnose(&provider, &state.canary).await])
+	})
+	.await?;
+	Ok(health_response(reports))
+}
+
+fn health_response(reports: Value) -> Response {
+	let failed = |report: &Value| {
+		report["stages"]
+			.as_array()
+			.is_none_or(|stages| stages.iter().any(|stage| !stage["error"].is_null()))
+	};
+	let healthy = reports
+		.as_array()
+		.is_some_and(|reports| !reports.iter().any(failed));
+	let status = match healthy {
+		true => StatusCode::OK,
+		false => StatusCode::SERVICE_UNAVAILABLE,
+	};
+
+	(status, Json(reports)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_client_ip() {
+		let addr: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+		let mut headers = HeaderMap::new();
+		headers.insert(
+			"x-forwarded-for",
+			"198.51.100.1, 203.0.113.7".parse().unwrap(),
+		);
+
+		assert_eq!(client_ip(&headers, addr, false), addr.ip());
+		assert_eq!(
+			client_ip(&headers, addr, true),
+			"203.0.113.7".parse::<IpAddr>().unwrap()
+		);
+		assert_eq!(client_ip(&HeaderMap::new(), addr, true), addr.ip());
+	}
+
+	#[cfg(feature = "hianime")]
+	#[tokio::test]
+	async fn test_legacy_routes() {
+		let location = |redirect: Result<Redirect, ApiError>| {
+			let response = redirect.ok().unwrap().into_response();
+			assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+			response.headers()["location"].to_str().unwrap().to_string()
+		};
+		let path = |a: &str, b: &str| Path((a.to_string(), b.to_string()));
+
+		let episodes = legacy_episodes(path("hianime", "100")).await;
+		assert_eq!(location(episodes), "/episodes/hianime:100");
+		let servers = legacy_servers(path("HiAnime", "100:12")).await;
+		assert_eq!(location(servers), "/servers/hianime:100:12");
+		let servers = legacy_servers(path("hianime", "hianime:100:12")).await;
+		assert_eq!(location(servers), "/servers/hianime:100:12");
+		assert!(legacy_servers(path("hianime", "abc")).await.is_err());
+	}
+}
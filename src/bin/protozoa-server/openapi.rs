@@ -0,0 +1,266 @@
+use protozoa::Provider;
+use serde_json::{json, Value};
+
+pub fn document() -> Value {
+	let providers: Vec<String> = Provider::ALL
+		.iter()
+		.map(|provider| provider.to_string().to_lowercase())
+		.collect();
+
+	let provider_query = json!({
+		"name": "provider", "in": "query", "required": true,
+		"schema": { "type": "string", "enum": providers },
+	});
+
+	json!({
+		"openapi": "3.1.0",
+		"info": {
+			"title": "protozoa",
+			"version": env!("CARGO_PKG_VERSION"),
+			"description": "Anime search, episode listing and stream resolution.",
+		},
+		"paths": {
+			"/search": {
+				"get": {
+					"summary": "Search a provider for a title",
+					"parameters": [provider_query, query("query", "string")],
+					"responses": responses(array("SearchResult")),
+				}
+			},
+			"/episodes/{id}": {
+				"get": {
+					"summary": "List the episodes of an anime",
+					"parameters": [path("id")],
+					"responses": responses(array("Episode")),
+				}
+			},
+			"/episodes/{provider}/{id}": {
+				"get": {
+					"summary": "Redirects to /episodes/{provider}:{id}",
+					"deprecated": true,
+					"parameters": [path("provider"), path("id")],
+					"responses": { "308": { "description": "Moved to /episodes/{id}" } },
+				}
+			},
+			"/related/{id}": {
+				"get": {
+					"summary": "List the other seasons and related entries of an anime",
+					"parameters": [path("id")],
+					"responses": responses(array("Related")),
+				}
+			},
+			"/servers/{id}": {
+				"get": {
+					"summary": "List the servers of an episode",
+					"parameters": [path("id")],
+					"responses": responses(array("Server")),
+				}
+			},
+			"/servers/{provider}/{ep_id}": {
+				"get": {
+					"summary": "Redirects to /servers/{id} for an episode id from /episodes",
+					"deprecated": true,
+					"parameters": [path("provider"), path("ep_id")],
+					"responses": { "308": { "description": "Moved to /servers/{id}" } },
+				}
+			},
+			"/source": {
+				"get": {
+					"summary": "Resolve a server id, or a provider and server url, to a playable source",
+					"parameters": [
+						optional(query("id", "string")),
+						optional(provider_query),
+						optional(query("url", "string")),
+						optional(query("proxy", "boolean")),
+					],
+					"responses": responses(reference("Source")),
+				}
+			},
+			"/skip": {
+				"get": {
+					"summary": "Opening, ending and recap times from AniSkip",
+					"parameters": [
+						query("title", "string"),
+						query("episode", "string"),
+						query("length", "number"),
+					],
+					"responses": responses(array("SkipTimes")),
+				}
+			},
+			"/proxy/{session}": {
+				"get": {
+					"summary": "Stream a proxied playlist, segment, key or caption with the upstream headers",
+					"parameters": [path("session"), query("url", "string")],
+					"responses": {
+						"200": { "description": "The upstream body, with playlists rewritten to go through the proxy" },
+						"404": { "description": "Unknown or expired session" },
+						"502": { "description": "The upstream site failed" },
+					},
+				}
+			},
+			"/health": {
+				"get": {
+					"summary": "Liveness check",
+					"responses": { "200": { "description": "The server is up" } },
+				}
+			},
+			"/metrics": {
+				"get": {
+					"summary": "Request counts, latencies and error kinds by provider and operation, in the Prometheus text format",
+					"responses": { "200": { "description": "The current metrics" } },
+				}
+			},
+			"/health/providers": {
+				"get": {
+					"summary": "Run every provider end-to-end",
+					"responses": {
+						"200": { "description": "Every provider resolved a source with a reachable playlist" },
+						"503": { "description": "At least one provider broke, see the failed stage" },
+					},
+				}
+			},
+			"/health/providers/{provider}": {
+				"get": {
+					"summary": "Run one provider end-to-end",
+					"parameters": [path("provider")],
+					"responses": {
+						"200": { "description": "The provider resolved a source with a reachable playlist" },
+						"400": { "description": "Unknown provider" },
+						"503": { "description": "The provider broke, see the failed stage and its latency" },
+					},
+				}
+			},
+		},
+		"components": {
+			"schemas": {
+				"SearchResult": object(json!({
+					"title": { "type": "string" },
+					"poster": { "type": "string" },
+					"id": id("provider:key"),
+				})),
+				"Related": object(json!({
+					"title": { "type": "string" },
+					"poster": { "type": ["string", "null"] },
+					"relation": {
+						"type": "string",
+						"enum": ["Season", "Sequel", "Prequel", "SideStory", "Movie", "Other"],
+					},
+					"format": {
+						"type": ["string", "null"],
+						"description": "TV, Movie, OVA, ONA, Special or Music"
+					},
+					"id": id("provider:key"),
+				})),
+				"Episode": object(json!({
+					"title": { "type": "string" },
+					"number": {
+						"type": ["integer", "string"],
+						"description": "A number for whole episodes like 12, otherwise a string like 6.5, SP1 or OVA2"
+					},
+					"id": id("provider:key:number"),
+					"japanese_title": { "type": ["string", "null"] },
+					"filler": { "type": ["boolean", "null"] },
+					"air_date": { "type": ["string", "null"] },
+					"thumbnail": { "type": ["string", "null"] },
+					"duration": { "type": ["integer", "null"], "description": "Length in seconds" },
+					"sub": { "type": ["boolean", "null"] },
+					"dub": { "type": ["boolean", "null"] },
+				})),
+				"Server": object(json!({
+					"name": { "type": "string" },
+					"locale": { "type": "string", "enum": ["HardSub", "SoftSub", "Dub", "Raw"] },
+					"url": { "type": "string" },
+					"id": id("provider:key:number:name"),
+				})),
+				"Source": object(json!({
+					"url": { "type": "string" },
+					"captions": { "type": "array", "items": reference("Caption") },
+					"headers": { "type": "object", "additionalProperties": { "type": "string" } },
+				})),
+				"Caption": object(json!({
+					"file": { "type": "string" },
+					"label": { "type": ["string", "null"] },
+					"kind": { "type": "string" },
+				})),
+				"SkipTimes": object(json!({
+					"start": { "type": "number" },
+					"end": { "type": "number" },
+					"skip_type": { "type": "string", "enum": ["Ed", "Op", "Recap"] },
+				})),
+				"Error": object(json!({ "error": { "type": "string" } })),
+			}
+		},
+	})
+}
+
+fn reference(name: &str) -> Value {
+	json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+// Ids are stable across requests and can be stored and sent back as is.
+fn id(format: &str) -> Value {
+	json!({ "type": "string", "description": format!("Stable id, written as {format}") })
+}
+
+fn array(name: &str) -> Value {
+	json!({ "type": "array", "items": reference(name) })
+}
+
+fn object(properties: Value) -> Value {
+	let required: Vec<&String> = properties
+		.as_object()
+		.map(|properties| properties.keys().collect())
+		.unwrap_or_default();
+	json!({ "type": "object", "required": required, "properties": properties })
+}
+
+fn query(name: &str, kind: &str) -> Value {
+	json!({ "name": name, "in": "query", "required": true, "schema": { "type": kind } })
+}
+
+fn optional(mut parameter: Value) -> Value {
+	parameter["required"] = json!(false);
+	parameter
+}
+
+fn path(name: &str) -> Value {
+	json!({ "name": name, "in": "path", "required": true, "schema": { "type": "string" } })
+}
+
+fn responses(schema: Value) -> Value {
+	let error = json!({ "application/json": { "schema": reference("Error") } });
+	json!({
+		"200": { "description": "OK", "content": { "application/json": { "schema": schema } } },
+		"400": { "description": "Unknown provider or missing parameter", "content": error },
+		"429": { "description": "Rate limit exceeded", "content": error },
+		"502": { "description": "The upstream site failed", "content": error },
+		"503": { "description": "The upstream site answered with a challenge", "content": error },
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_document() {
+		let document = document();
+		for path in [
+			"/search",
+			"/episodes/{id}",
+			"/related/{id}",
+			"/servers/{id}",
+			"/source",
+			"/skip",
+		] {
+			assert!(
+				document["paths"][path]["get"]["responses"]["200"].is_object(),
+				"{path}"
+			);
+		}
+		assert_eq!(
+			document["components"]["schemas"]["Caption"]["required"],
+			json!(["file", "kind", "label"])
+		);
+	}
+}
@@ -0,0 +1,80 @@
+use std::{fmt, fs, path::PathBuf};
+
+use anyhow::Context as _;
+use protozoa::{AnimeId, EpisodeNumber};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const LIMIT: usize = 50;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+	pub id: AnimeId,
+	pub title: String,
+	pub episode: EpisodeNumber,
+}
+
+impl fmt::Display for Entry {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} · episode {} [{}]",
+			self.title, self.episode, self.id.provider
+		)
+	}
+}
+
+// Most recently watched first, one entry per anime.
+pub struct History {
+	path: PathBuf,
+	entries: Vec<Entry>,
+}
+
+impl History {
+	pub fn load() -> Result<Self, anyhow::Error> {
+		let path = dirs::data_dir()
+			.context("No data directory")?
+			.join("protozoa")
+			.join("history.json");
+
+		// Entries from older versions or for providers that aren't enabled are skipped.
+		let entries = match fs::read_to_string(&path) {
+			Ok(json) => serde_json::from_str::<Vec<Value>>(&json)
+				.context("Failed to parse history")?
+				.into_iter()
+				.filter_map(|entry| serde_json::from_value(entry).ok())
+				.collect(),
+			Err(_) => Vec::new(),
+		};
+
+		Ok(History { path, entries })
+	}
+
+	pub fn entries(&self) -> &[Entry] {
+		&self.entries
+	}
+
+	pub fn find(&self, id: &AnimeId) -> Option<&Entry> {
+		self.entries.iter().find(|entry| entry.id == *id)
+	}
+
+	pub fn record(
+		&mut self, id: &AnimeId, title: &str, episode: EpisodeNumber,
+	) -> Result<(), anyhow::Error> {
+		let entry = Entry {
+			id: id.clone(),
+			title: title.to_string(),
+			episode,
+		};
+
+		self.entries.retain(|e| e.id != entry.id);
+		self.entries.insert(0, entry);
+		self.entries.truncate(LIMIT);
+
+		if let Some(dir) = self.path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(&self.path, serde_json::to_string_pretty(&self.entries)?)?;
+		Ok(())
+	}
+}
@@ -0,0 +1,266 @@
+mod history;
+mod mpv;
+mod playlist;
+
+use std::fmt;
+
+use history::History;
+use inquire::{InquireError, Select, Text};
+use protozoa::{Locale, Provider, SearchResult};
+
+struct Hit {
+	provider: Provider,
+	result: SearchResult,
+}
+
+impl fmt::Display for Hit {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} [{}]", self.result.title, self.provider)
+	}
+}
+
+enum Menu {
+	Search,
+	Continue(history::Entry),
+	Quit,
+}
+
+impl fmt::Display for Menu {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Menu::Search => write!(f, "Search"),
+			Menu::Continue(entry) => write!(f, "Continue {entry}"),
+			Menu::Quit => write!(f, "Quit"),
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+	Next,
+	Previous,
+	Replay,
+	Episodes,
+	Search,
+	Quit,
+}
+
+impl fmt::Display for Action {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Action::Next => write!(f, "Next episode"),
+			Action::Previous => write!(f, "Previous episode"),
+			Action::Replay => write!(f, "Replay"),
+			Action::Episodes => write!(f, "Pick episode"),
+			Action::Search => write!(f, "Search"),
+			Action::Quit => write!(f, "Quit"),
+		}
+	}
+}
+
+// Remembered for the session so every episode doesn't ask again.
+#[derive(Default)]
+struct Preferences {
+	locale: Option<Locale>,
+	height: Option<u32>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+	let mut history = History::load()?;
+	let mut preferences = Preferences::default();
+
+	loop {
+		let hit = match pick_anime(&history).await {
+			Ok(Some(hit)) => hit,
+			Ok(None) => return Ok(()),
+			Err(e) if is_interrupt(&e) => return Ok(()),
+			Err(e) => {
+				eprintln!("{e:#}");
+				continue;
+			}
+		};
+
+		match watch(&mut history, &mut preferences, &hit).await {
+			Ok(Action::Quit) => return Ok(()),
+			Ok(_) => (),
+			Err(e) if is_interrupt(&e) => return Ok(()),
+			Err(e) => eprintln!("{e:#}"),
+		}
+	}
+}
+
+async fn pick_anime(history: &History) -> Result<Option<Hit>, anyhow::Error> {
+	let mut menu = vec![Menu::Search];
+	menu.extend(history.entries().iter().cloned().map(Menu::Continue));
+	menu.push(Menu::Quit);
+
+	let choice = match menu.len() {
+		2 => Menu::Search,
+		_ => Select::new("protozoa", menu).prompt()?,
+	};
+
+	match choice {
+		Menu::Search => search().await.map(Some),
+		Menu::Continue(entry) => Ok(Some(Hit {
+			provider: entry.id.provider,
+			result: SearchResult {
+				title: entry.title,
+				poster: String::new(),
+				id: entry.id,
+			},
+		})),
+		Menu::Quit => Ok(None),
+	}
+}
+
+async fn search() -> Result<Hit, anyhow::Error> {
+	let query = Text::new("Search:").prompt()?;
+
+	let mut hits = Vec::new();
+	for provider in Provider::ALL {
+		match protozoa::search(provider, &query).await {
+			Ok(results) => hits.extend(results.into_iter().map(|result| Hit {
+				provider: *provider,
+				result,
+			})),
+			Err(e) => eprintln!("{provider}: {e:#}"),
+		}
+	}
+
+	anyhow::ensure!(!hits.is_empty(), "Nothing found for {query}");
+	Ok(Select::new("Anime:", hits).prompt()?)
+}
+
+async fn watch(
+	history: &mut History, preferences: &mut Preferences, hit: &Hit,
+) -> Result<Action, anyhow::Error> {
+	let Hit { result, .. } = hit;
+	let episodes = protozoa::episodes(&result.id).await?;
+	anyhow::ensure!(!episodes.is_empty(), "No episodes");
+
+	// Continue right after the last watched episode.
+	let start = history
+		.find(&result.id)
+		.and_then(|entry| episodes.iter().position(|e| e.number == entry.episode))
+		.map(|index| (index + 1).min(episodes.len() - 1))
+		.unwrap_or(0);
+
+	let labels: Vec<String> = episodes
+		.iter()
+		.map(|e| match e.filler {
+			Some(true) => format!("{}. {} (filler)", e.number, e.title),
+			_ => format!("{}. {}", e.number, e.title),
+		})
+		.collect();
+	let pick = |cursor: usize| -> Result<usize, InquireError> {
+		let choice = Select::new("Episode:", labels.clone())
+			.with_starting_cursor(cursor)
+			.raw_prompt()?;
+		Ok(choice.index)
+	};
+
+	let mut index = pick(start)?;
+	loop {
+		let episode = &episodes[index];
+		if let Err(e) = play(preferences, hit, episode).await {
+			eprintln!("{e:#}");
+		}
+		history.record(&result.id, &result.title, episode.number)?;
+
+		let mut actions = Vec::new();
+		if index + 1 < episodes.len() {
+			actions.push(Action::Next);
+		}
+		if index > 0 {
+			actions.push(Action::Previous);
+		}
+		actions.extend([
+			Action::Replay,
+			Action::Episodes,
+			Action::Search,
+			Action::Quit,
+		]);
+
+		match Select::new("Next:", actions).prompt()? {
+			Action::Next => index += 1,
+			Action::Previous => index -= 1,
+			Action::Replay => (),
+			Action::Episodes => index = pick(index)?,
+			action => return Ok(action),
+		}
+	}
+}
+
+async fn play(
+	preferences: &mut Preferences, hit: &Hit, episode: &protozoa::Episode,
+) -> Result<(), anyhow::Error> {
+	let servers = protozoa::servers(&episode.id).await?;
+	anyhow::ensure!(!servers.is_empty(), "No servers");
+
+	let mut locales: Vec<Locale> = Vec::new();
+	for server in &servers {
+		if !locales.contains(&server.locale) {
+			locales.push(server.locale);
+		}
+	}
+	let locale = match preferences.locale.filter(|l| locales.contains(l)) {
+		Some(locale) => locale,
+		None if locales.len() == 1 => locales[0],
+		None => Select::new("Locale:", locales).prompt()?,
+	};
+	preferences.locale = Some(locale);
+
+	// Servers of the chosen locale in provider order, stopping at the first that resolves.
+	let mut source = None;
+	for server in servers.iter().filter(|s| s.locale == locale) {
+		match protozoa::get_source(&hit.provider, &server.url).await {
+			Ok(resolved) => {
+				source = Some(resolved);
+				break;
+			}
+			Err(e) => eprintln!("{server}: {e:#}"),
+		}
+	}
+	let source = source.ok_or_else(|| anyhow::anyhow!("No server resolved"))?;
+
+	let variants = playlist::variants(&source).await.unwrap_or_default();
+	let url = match variants.len() {
+		0 => source.url.clone(),
+		1 => variants[0].url.clone(),
+		_ => {
+			let preferred = preferences
+				.height
+				.and_then(|height| variants.iter().position(|v| v.height == Some(height)));
+			let variant = match preferred {
+				Some(index) => &variants[index],
+				None => {
+					let index = Select::new(
+						"Quality:",
+						variants.iter().map(ToString::to_string).collect(),
+					)
+					.raw_prompt()?
+					.index;
+					&variants[index]
+				}
+			};
+			preferences.height = variant.height;
+			variant.url.clone()
+		}
+	};
+
+	let media_title = format!("{} - {}", hit.result.title, episode.title);
+	let skip = mpv::Skip {
+		title: hit.result.title.clone(),
+		episode: episode.id.clone(),
+	};
+	mpv::play(&media_title, &url, &source, skip).await?;
+	Ok(())
+}
+
+fn is_interrupt(error: &anyhow::Error) -> bool {
+	matches!(
+		error.downcast_ref::<InquireError>(),
+		Some(InquireError::OperationCanceled | InquireError::OperationInterrupted)
+	)
+}
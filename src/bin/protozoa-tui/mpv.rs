@@ -0,0 +1,155 @@
+use std::process::{Command, ExitStatus};
+
+use anyhow::Context as _;
+use protozoa::{EpisodeId, Source};
+
+// Numbers each playback's socket, so a previous episode's auto-skip thread can't attach to or remove the next one's.
+#[cfg(unix)]
+static PLAYBACKS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+pub struct Skip {
+	pub title: String,
+	pub episode: EpisodeId,
+}
+
+pub async fn play(
+	media_title: &str, url: &str, source: &Source, skip: Skip,
+) -> Result<ExitStatus, anyhow::Error> {
+	let mut command = Command::new("mpv");
+	command
+		.arg(url)
+		.arg(format!("--force-media-title={media_title}"));
+	for (name, value) in &source.headers {
+		command.arg(format!("--http-header-fields-append={name}: {value}"));
+	}
+	for caption in &source.captions {
+		command.arg(format!("--sub-file={}", caption.url));
+	}
+
+	#[cfg(unix)]
+	let socket = std::env::temp_dir().join(format!(
+		"protozoa-mpv-{}-{}.sock",
+		std::process::id(),
+		PLAYBACKS.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+	));
+	#[cfg(unix)]
+	command.arg(format!("--input-ipc-server={}", socket.display()));
+
+	let mut child = command
+		.spawn()
+		.context("Failed to launch mpv, is it installed?")?;
+
+	#[cfg(unix)]
+	{
+		let handle = tokio::runtime::Handle::current();
+		let socket = socket.clone();
+		std::thread::spawn(move || ipc::auto_skip(&socket, skip, handle));
+	}
+	#[cfg(not(unix))]
+	let _ = skip;
+
+	let status = tokio::task::spawn_blocking(move || child.wait()).await;
+	// mpv leaves the socket behind when it's killed, and auto-skip may have given up before it appeared.
+	#[cfg(unix)]
+	ipc::remove(&socket);
+	Ok(status??)
+}
+
+#[cfg(unix)]
+mod ipc {
+	use std::{
+		io::{BufRead as _, BufReader, Write as _},
+		os::unix::net::UnixStream,
+		path::Path,
+		thread,
+		time::Duration,
+	};
+
+	use protozoa::aniskip;
+	use serde_json::{json, Value};
+	use tokio::runtime::Handle;
+
+	use super::Skip;
+
+	// Skip times depend on the episode length, so they are fetched once mpv reports the duration.
+	pub fn auto_skip(socket: &Path, skip: Skip, handle: Handle) -> Result<(), anyhow::Error> {
+		let _cleanup = Cleanup(socket);
+		let stream = connect(socket)?;
+		let mut writer = stream.try_clone()?;
+		for (id, property) in [(1, "duration"), (2, "time-pos")] {
+			writeln!(
+				writer,
+				"{}",
+				json!({ "command": ["observe_property", id, property] })
+			)?;
+		}
+
+		let mut skip_times = None;
+		for line in BufReader::new(stream).lines() {
+			let Ok(event) = serde_json::from_str::<Value>(&line?) else {
+				continue;
+			};
+			if event["event"] != "property-change" {
+				continue;
+			}
+			let Some(value) = event["data"].as_f64() else {
+				continue;
+			};
+
+			match event["name"].as_str() {
+				Some("duration") if skip_times.is_none() => {
+					let fetched = handle.block_on(aniskip::get_episode_skip_times(
+						&skip.title,
+						&skip.episode,
+						value as f32,
+					));
+					skip_times = Some(fetched.unwrap_or_default());
+				}
+				Some("time-pos") => {
+					let Some(times) = skip_times.as_mut() else {
+						continue;
+					};
+					let position = value as f32;
+					if let Some(index) = times
+						.iter()
+						.position(|t| position >= t.start && position < t.end - 1.)
+					{
+						let end = times.remove(index).end;
+						writeln!(
+							writer,
+							"{}",
+							json!({ "command": ["set_property", "time-pos", end] })
+						)?;
+					}
+				}
+				_ => (),
+			}
+		}
+
+		Ok(())
+	}
+
+	pub fn remove(socket: &Path) {
+		let _ = std::fs::remove_file(socket);
+	}
+
+	// Removes the socket however auto-skip ends, including when it never connects or the connection breaks.
+	struct Cleanup<'a>(&'a Path);
+
+	impl Drop for Cleanup<'_> {
+		fn drop(&mut self) {
+			remove(self.0);
+		}
+	}
+
+	// mpv creates the socket shortly after starting.
+	fn connect(socket: &Path) -> Result<UnixStream, anyhow::Error> {
+		for _ in 0..50 {
+			if let Ok(stream) = UnixStream::connect(socket) {
+				return Ok(stream);
+			}
+			thread::sleep(Duration::from_millis(100));
+		}
+		anyhow::bail!("mpv IPC socket never appeared")
+	}
+}
@@ -0,0 +1,102 @@
+use std::{cmp::Reverse, fmt};
+
+use protozoa::Source;
+use reqwest::{Client, Url};
+
+#[derive(Debug, PartialEq)]
+pub struct Variant {
+	pub height: Option<u32>,
+	pub bandwidth: Option<u64>,
+	pub url: String,
+}
+
+impl fmt::Display for Variant {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match (self.height, self.bandwidth) {
+			(Some(height), _) => write!(f, "{height}p"),
+			(None, Some(bandwidth)) => write!(f, "{} kbps", bandwidth / 1000),
+			(None, None) => write!(f, "{}", self.url),
+		}
+	}
+}
+
+// Variant streams of an HLS master playlist, best first; empty for media playlists and plain files.
+pub async fn variants(source: &Source) -> Result<Vec<Variant>, anyhow::Error> {
+	if !source.url.contains(".m3u8") {
+		return Ok(Vec::new());
+	}
+
+	let mut request = Client::new().get(&source.url);
+	for (name, value) in &source.headers {
+		request = request.header(name, value);
+	}
+	let playlist = request.send().await?.error_for_status()?.text().await?;
+
+	Ok(parse(&playlist, &Url::parse(&source.url)?))
+}
+
+fn parse(playlist: &str, base: &Url) -> Vec<Variant> {
+	let mut variants = Vec::new();
+	let mut lines = playlist.lines().map(str::trim);
+
+	while let Some(line) = lines.next() {
+		let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") else {
+			continue;
+		};
+		let Some(uri) = lines.find(|line| !line.is_empty() && !line.starts_with('#')) else {
+			break;
+		};
+		let Ok(url) = base.join(uri) else {
+			continue;
+		};
+
+		let attribute = |name: &str| {
+			attributes
+				.split(',')
+				.find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+		};
+		variants.push(Variant {
+			height: attribute("RESOLUTION")
+				.and_then(|resolution| resolution.split_once('x')?.1.parse().ok()),
+			bandwidth: attribute("BANDWIDTH").and_then(|bandwidth| bandwidth.parse().ok()),
+			url: url.to_string(),
+		});
+	}
+
+	variants.sort_by_key(|v| Reverse((v.height, v.bandwidth)));
+	variants
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse() {
+		let master = "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360\nindex-360.m3u8\n#EXT-X-STREAM-INF:BANDWIDTH=2800000,RESOLUTION=1920x1080,CODECS=\"avc1.640028,mp4a.40.2\"\n\nhttps://cdn.example.com/index-1080.m3u8\n";
+		let variants = parse(
+			master,
+			&Url::parse("https://cdn.example.com/hls/master.m3u8").unwrap(),
+		);
+		assert_eq!(
+			variants,
+			vec![
+				Variant {
+					height: Some(1080),
+					bandwidth: Some(2800000),
+					url: "https://cdn.example.com/index-1080.m3u8".to_string(),
+				},
+				Variant {
+					height: Some(360),
+					bandwidth: Some(800000),
+					url: "https://cdn.example.com/hls/index-360.m3u8".to_string(),
+				},
+			]
+		);
+		assert!(parse(
+			"#EXTM3U\n#EXTINF:10,\nseg0.ts\n",
+			&Url::parse("https://a.b/").unwrap()
+		)
+		.is_empty());
+	}
+}
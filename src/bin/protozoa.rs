@@ -0,0 +1,311 @@
+use std::{
+	env,
+	path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use clap::{Parser, Subcommand};
+use protozoa::{
+	aniskip,
+	antibot::{self, cloudflare::FlareSolverr},
+	diagnose, mirrors, telemetry, AnimeId, EpisodeId, EpisodeNumber, Provider, ServerId, Source,
+};
+use serde::Serialize;
+use tokio::{fs::File, io::AsyncWriteExt as _, process::Command};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser)]
+#[command(
+	name = "protozoa",
+	version,
+	about = "Search anime providers and resolve streams"
+)]
+struct Cli {
+	/// Provider to query (hianime, animekai, animepahe)
+	#[arg(short, long, global = true, value_parser = parse_provider)]
+	provider: Option<Provider>,
+
+	/// Print JSON instead of tables
+	#[arg(long, global = true)]
+	json: bool,
+
+	/// FlareSolverr endpoint used to get past Cloudflare challenges, e.g. http://localhost:8191
+	#[arg(long, global = true)]
+	flaresolverr: Option<String>,
+
+	/// Mirrors to try in order for a site, e.g. hianime=https://hianime.sx,https://hianime.to (repeatable)
+	#[arg(long, global = true)]
+	mirror: Vec<String>,
+
+	/// Log requests and decrypt stages to stderr, -vv for trace level (RUST_LOG overrides this)
+	#[arg(short, long, global = true, action = clap::ArgAction::Count)]
+	verbose: u8,
+
+	/// Also log raw response bodies at trace level, to debug selectors that stopped matching
+	#[arg(long, global = true)]
+	dump_bodies: bool,
+
+	#[command(subcommand)]
+	command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+	/// Search for a title
+	Search { query: String },
+	/// List the episodes of an anime id from `search`, e.g. hianime:100
+	Episodes { id: AnimeId },
+	/// List the other seasons, sequels, movies and side stories of an anime id
+	Related { id: AnimeId },
+	/// List the servers of an episode id from `episodes`, e.g. hianime:100:1
+	Servers { id: EpisodeId },
+	/// Resolve a server url (with --provider) or a server id from `servers` to a playable source
+	Source { server: String },
+	/// Look up opening/ending skip times on AniSkip
+	Skip {
+		title: String,
+		episode: EpisodeNumber,
+		/// Episode length in seconds
+		#[arg(short, long, default_value_t = 1440.)]
+		length: f32,
+	},
+	/// Resolve a server url or id and save the video, using ffmpeg for HLS playlists
+	Download {
+		server: String,
+		#[arg(short, long, default_value = "episode.mp4")]
+		output: PathBuf,
+	},
+	/// Run search -> episodes -> servers -> source -> playlist and report the stage that breaks, with latencies
+	Diagnose {
+		#[arg(default_value = "One Piece")]
+		query: String,
+	},
+}
+
+fn parse_provider(s: &str) -> Result<Provider, String> {
+	Provider::from(s).ok_or_else(|| {
+		let known: Vec<String> = Provider::ALL
+			.iter()
+			.map(|p| p.to_string().to_lowercase())
+			.collect();
+		format!("unknown provider, expected one of: {}", known.join(", "))
+	})
+}
+
+fn init_tracing(verbose: u8, dump_bodies: bool) {
+	let level = match verbose {
+		0 => "warn",
+		1 => "debug",
+		_ => "trace",
+	};
+	let body = match dump_bodies {
+		true => "trace",
+		false => "off",
+	};
+	let directives = match env::var(EnvFilter::DEFAULT_ENV) {
+		Ok(directives) if dump_bodies => format!("{directives},{}=trace", telemetry::BODY_TARGET),
+		Ok(directives) => directives,
+		Err(_) => format!(
+			"warn,protozoa={level},protozoa_cryptography={level},{}={body}",
+			telemetry::BODY_TARGET
+		),
+	};
+	tracing_subscriber::fmt()
+		.with_env_filter(EnvFilter::new(directives))
+		.with_writer(std::io::stderr)
+		.init();
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+	let cli = Cli::parse();
+	init_tracing(cli.verbose, cli.dump_bodies);
+	if let Some(endpoint) = &cli.flaresolverr {
+		antibot::set_solver(FlareSolverr::new(endpoint));
+	}
+	for mirror in &cli.mirror {
+		mirrors::set_from_str(mirror)?;
+	}
+	let provider = || {
+		cli.provider
+			.context("--provider is required for this command")
+	};
+
+	match &cli.command {
+		Commands::Search { query } => {
+			let results = protozoa::search(&provider()?, query).await?;
+			print(cli.json, &results, &["ID", "TITLE"], |r| {
+				vec![r.id.to_string(), r.title.clone()]
+			})
+		}
+		Commands::Episodes { id } => {
+			let episodes = protozoa::episodes(id).await?;
+			print(cli.json, &episodes, &["#", "ID", "TITLE"], |e| {
+				vec![e.number.to_string(), e.id.to_string(), e.title.clone()]
+			})
+		}
+		Commands::Related { id } => {
+			let related = protozoa::related(id).await?;
+			print(cli.json, &related, &["ID", "RELATION", "TITLE"], |r| {
+				vec![r.id.to_string(), r.relation.to_string(), r.title.clone()]
+			})
+		}
+		Commands::Servers { id } => {
+			let servers = protozoa::servers(id).await?;
+			print(cli.json, &servers, &["ID", "LOCALE", "URL"], |s| {
+				vec![s.id.to_string(), s.locale.to_string(), s.url.clone()]
+			})
+		}
+		Commands::Source { server } => {
+			let source = resolve(cli.provider, server).await?;
+			if cli.json {
+				println!("{}", serde_json::to_string_pretty(&source)?);
+			} else {
+				println!("{}", source.url);
+				let captions = source.captions.iter().map(|c| {
+					vec![
+						c.label.clone().unwrap_or_default(),
+						c.kind.clone(),
+						c.url.clone(),
+					]
+				});
+				table(&["LABEL", "KIND", "CAPTION"], captions.collect());
+				let headers = source
+					.headers
+					.iter()
+					.map(|(k, v)| vec![k.clone(), v.clone()]);
+				table(&["HEADER", "VALUE"], headers.collect());
+			}
+			Ok(())
+		}
+		Commands::Skip {
+			title,
+			episode,
+			length,
+		} => {
+			let skip_times = aniskip::get_skip_times(title, *episode, *length).await?;
+			print(cli.json, &skip_times, &["TYPE", "START", "END"], |s| {
+				vec![
+					format!("{:?}", s.skip_type),
+					format!("{:.3}", s.start),
+					format!("{:.3}", s.end),
+				]
+			})
+		}
+		Commands::Download { server, output } => {
+			let source = resolve(cli.provider, server).await?;
+			download(&source, output).await?;
+			eprintln!("Saved {}", output.display());
+			Ok(())
+		}
+		Commands::Diagnose { query } => {
+			let reports = match cli.provider {
+				Some(provider) => vec![diagnose::diagnose(&provider, query).await],
+				None => diagnose::diagnose_all(query).await,
+			};
+			if cli.json {
+				println!("{}", serde_json::to_string_pretty(&reports)?);
+			} else {
+				reports.iter().for_each(|report| print!("{report}"));
+			}
+			anyhow::ensure!(
+				reports.iter().all(|r| r.is_ok()),
+				"Some providers are broken"
+			);
+			Ok(())
+		}
+	}
+}
+
+// Server urls need --provider, server ids carry their own.
+async fn resolve(provider: Option<Provider>, server: &str) -> Result<Source, anyhow::Error> {
+	match provider {
+		Some(provider) => protozoa::get_source(&provider, server).await,
+		None => {
+			let id: ServerId = server
+				.parse()
+				.context("Expected a server id, or a url with --provider")?;
+			protozoa::source(&id).await
+		}
+	}
+}
+
+fn print<T: Serialize>(
+	json: bool, items: &[T], headers: &[&str], row: impl Fn(&T) -> Vec<String>,
+) -> Result<(), anyhow::Error> {
+	if json {
+		println!("{}", serde_json::to_string_pretty(items)?);
+	} else {
+		table(headers, items.iter().map(row).collect());
+	}
+	Ok(())
+}
+
+fn table(headers: &[&str], rows: Vec<Vec<String>>) {
+	if rows.is_empty() {
+		return;
+	}
+
+	let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+	for row in &rows {
+		for (width, cell) in widths.iter_mut().zip(row) {
+			*width = (*width).max(cell.chars().count());
+		}
+	}
+
+	let line = |cells: Vec<&str>| {
+		let padded: Vec<String> = cells
+			.iter()
+			.zip(&widths)
+			.map(|(cell, width)| format!("{cell:<width$}"))
+			.collect();
+		println!("{}", padded.join("  ").trim_end());
+	};
+
+	line(headers.to_vec());
+	for row in &rows {
+		line(row.iter().map(String::as_str).collect());
+	}
+}
+
+async fn download(source: &Source, output: &Path) -> Result<(), anyhow::Error> {
+	if source.url.contains(".m3u8") {
+		let headers: String = source
+			.headers
+			.iter()
+			.map(|(name, value)| format!("{name}: {value}\r\n"))
+			.collect();
+		let status = Command::new("ffmpeg")
+			.args([
+				"-loglevel",
+				"error",
+				"-stats",
+				"-y",
+				"-headers",
+				&headers,
+				"-i",
+				&source.url,
+			])
+			.args(["-c", "copy"])
+			.arg(output)
+			.status()
+			.await
+			.context("Failed to run ffmpeg, is it installed?")?;
+		anyhow::ensure!(status.success(), "ffmpeg exited with {status}");
+		return Ok(());
+	}
+
+	let client = reqwest::Client::new();
+	let mut request = client.get(&source.url);
+	for (name, value) in &source.headers {
+		request = request.header(name, value);
+	}
+
+	let mut response = request.send().await?.error_for_status()?;
+	let mut file = File::create(output).await?;
+	while let Some(chunk) = response.chunk().await? {
+		file.write_all(&chunk).await?;
+	}
+	file.flush().await?;
+	Ok(())
+}
@@ -0,0 +1,194 @@
+mod packer;
+
+use lazy_static::lazy_static;
+use packer::Cursor;
+pub use packer::{is_packed, unpack, unpack_all};
+use regex::Regex;
+use serde_json::Value;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+	#[error("no packed script found")]
+	NotPacked,
+	#[error("malformed packed script: {0}")]
+	Malformed(&'static str),
+	#[error("unsupported radix {0}")]
+	InvalidRadix(u32),
+}
+
+pub fn media_urls(source: &str) -> Vec<String> {
+	lazy_static! {
+		static ref MEDIA: Regex =
+			Regex::new(r#"https?:(?:\\?/){2}[^\s"'<>`]+?\.(?:m3u8|mp4)(?:\?[^\s"'<>`]*)?"#)
+				.unwrap();
+	}
+
+	let mut urls: Vec<String> = Vec::new();
+	for found in MEDIA.find_iter(source) {
+		let url = found.as_str().replace("\\/", "/");
+		if !urls.contains(&url) {
+			urls.push(url);
+		}
+	}
+
+	urls
+}
+
+// Finds the first object or array literal assigned to `key` (`key: {...}`, `"key": [...]`, `key = {...}`).
+pub fn find_json(source: &str, key: &str) -> Option<Value> {
+	lazy_static! {
+		static ref ASSIGNMENT: Regex =
+			Regex::new(r#"(?:^|[^\w$])(?:"([^"]*)"|'([^']*)'|([\w$]+))\s*[:=]\s*[\[{]"#).unwrap();
+	}
+
+	// Every assignment is matched and compared by name, resuming at its bracket so nested keys are seen too.
+	let mut position = 0;
+	while let Some(captures) = ASSIGNMENT.captures_at(source, position) {
+		let start = captures.get(0).unwrap().end() - 1;
+		position = start;
+		let name = (1..=3)
+			.find_map(|group| captures.get(group))
+			.unwrap()
+			.as_str();
+		if name != key {
+			continue;
+		}
+
+		let value = balanced(source, start)
+			.and_then(|literal| serde_json::from_str(&to_json(literal)?).ok());
+		if value.is_some() {
+			return value;
+		}
+	}
+
+	None
+}
+
+fn balanced(source: &str, start: usize) -> Option<&str> {
+	let mut cursor = Cursor::new(source, start);
+	let mut depth = 0;
+
+	loop {
+		match cursor.peek()? {
+			'\'' | '"' => {
+				cursor.string().ok()?;
+				continue;
+			}
+			'{' | '[' => depth += 1,
+			'}' | ']' => {
+				depth -= 1;
+				if depth == 0 {
+					cursor.next();
+					return Some(&source[start..cursor.position()]);
+				}
+			}
+			_ => (),
+		}
+		cursor.next();
+	}
+}
+
+// Converts a JS object literal into JSON: quotes bare keys, normalizes strings and drops trailing commas.
+fn to_json(literal: &str) -> Option<String> {
+	let mut cursor = Cursor::new(literal, 0);
+	let mut json = String::with_capacity(literal.len());
+
+	while let Some(c) = cursor.peek() {
+		match c {
+			'\'' | '"' => {
+				let value = cursor.string().ok()?;
+				json.push_str(&serde_json::to_string(&value).ok()?);
+				continue;
+			}
+			'!' => {
+				cursor.next();
+				match cursor.next()? {
+					'0' => json.push_str("true"),
+					'1' => json.push_str("false"),
+					_ => return None,
+				}
+				continue;
+			}
+			',' => {
+				cursor.next();
+				cursor.skip_whitespace();
+				if !matches!(cursor.peek(), Some('}' | ']')) {
+					json.push(',');
+				}
+				continue;
+			}
+			c if c.is_alphabetic() || c == '_' || c == '$' => {
+				let identifier = cursor.identifier();
+				cursor.skip_whitespace();
+				match identifier {
+					_ if cursor.peek() == Some(':') => {
+						json.push_str(&serde_json::to_string(identifier).ok()?)
+					}
+					"true" | "false" | "null" => json.push_str(identifier),
+					_ => json.push_str("null"),
+				}
+				continue;
+			}
+			c => json.push(c),
+		}
+		cursor.next();
+	}
+
+	Some(json)
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+
+	const PLAYER: &str = r#"var player=jwplayer("vplayer");player.setup({sources:[{file:"https://cdn.example.com/hls/abc/master.m3u8?t=1&s=2",type:'hls'}],image:"https://cdn.example.com/abc.jpg",tracks:[{file:"https:\/\/cdn.example.com\/abc_eng.vtt",label:"English",kind:"captions",'default':!0},],autostart:false,width:"100%"});var fallback="https://cdn.example.com/abc.mp4";"#;
+
+	#[test]
+	fn test_media_urls() {
+		assert_eq!(
+			media_urls(PLAYER),
+			vec![
+				"https://cdn.example.com/hls/abc/master.m3u8?t=1&s=2",
+				"https://cdn.example.com/abc.mp4",
+			]
+		);
+		assert_eq!(
+			media_urls(r#"{"file":"https:\/\/cdn.example.com\/a.m3u8"}"#),
+			vec!["https://cdn.example.com/a.m3u8"]
+		);
+	}
+
+	#[test]
+	fn test_find_json() {
+		assert_eq!(
+			find_json(PLAYER, "sources"),
+			Some(
+				json!([{ "file": "https://cdn.example.com/hls/abc/master.m3u8?t=1&s=2", "type": "hls" }])
+			)
+		);
+		assert_eq!(
+			find_json(PLAYER, "tracks"),
+			Some(json!([{
+				"file": "https://cdn.example.com/abc_eng.vtt",
+				"label": "English",
+				"kind": "captions",
+				"default": true,
+			}]))
+		);
+		assert_eq!(find_json(PLAYER, "missing"), None);
+	}
+
+	#[test]
+	fn test_find_json_assignment() {
+		let source =
+			r#"var config = {"url": "https://example.com/a.m3u8", "skip": {intro: [1, 90]}};"#;
+		assert_eq!(
+			find_json(source, "config"),
+			Some(json!({ "url": "https://example.com/a.m3u8", "skip": { "intro": [1, 90] } }))
+		);
+		assert_eq!(find_json(source, "intro"), Some(json!([1, 90])));
+		assert_eq!(find_json(source, "url"), None);
+	}
+}
@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use super::Error;
+
+const HEADER: &str = "eval(function(p,a,c,k,e,";
+
+// Packed output can itself be packed; real players rarely go deeper than two levels.
+const MAX_DEPTH: usize = 8;
+
+struct Packed {
+	start: usize,
+	end: usize,
+	payload: String,
+	radix: u32,
+	count: usize,
+	symtab: Vec<String>,
+}
+
+pub fn is_packed(source: &str) -> bool {
+	source.contains(HEADER)
+}
+
+pub fn unpack(source: &str) -> Result<String, Error> {
+	let mut source = unpack_once(source)?;
+	for _ in 0..MAX_DEPTH {
+		if !is_packed(&source) {
+			break;
+		}
+		source = unpack_once(&source)?;
+	}
+
+	Ok(source)
+}
+
+pub fn unpack_all(source: &str) -> Result<Vec<String>, Error> {
+	let blocks = find_blocks(source)?;
+	if blocks.is_empty() {
+		return Err(Error::NotPacked);
+	}
+
+	blocks
+		.iter()
+		.map(|block| {
+			let unpacked = block.unpack();
+			if is_packed(&unpacked) {
+				unpack(&unpacked)
+			} else {
+				Ok(unpacked)
+			}
+		})
+		.collect()
+}
+
+fn unpack_once(source: &str) -> Result<String, Error> {
+	let blocks = find_blocks(source)?;
+	if blocks.is_empty() {
+		return Err(Error::NotPacked);
+	}
+
+	let mut output = String::with_capacity(source.len());
+	let mut last = 0;
+	for block in blocks {
+		output.push_str(&source[last..block.start]);
+		output.push_str(&block.unpack());
+		last = block.end;
+	}
+	output.push_str(&source[last..]);
+
+	Ok(output)
+}
+
+fn find_blocks(source: &str) -> Result<Vec<Packed>, Error> {
+	let mut blocks = Vec::new();
+	let mut offset = 0;
+
+	while let Some(index) = source[offset..].find(HEADER) {
+		let start = offset + index;
+		let block = parse_block(source, start)?;
+		offset = block.end;
+		blocks.push(block);
+	}
+
+	Ok(blocks)
+}
+
+fn parse_block(source: &str, start: usize) -> Result<Packed, Error> {
+	let mut cursor = Cursor::new(source, start + HEADER.len());
+
+	// The arguments follow the first `}(` that opens with a string literal.
+	loop {
+		let index = source[cursor.position..]
+			.find("}(")
+			.ok_or(Error::Malformed("missing arguments"))?;
+		cursor.position += index + 2;
+		cursor.skip_whitespace();
+		if cursor.peek().is_some_and(|c| c == '\'' || c == '"') {
+			break;
+		}
+	}
+
+	let payload = cursor.string()?;
+	cursor.expect(',')?;
+	let radix =
+		u32::try_from(cursor.integer()?).map_err(|_| Error::Malformed("radix out of range"))?;
+	cursor.expect(',')?;
+	let count = cursor.integer()?;
+	cursor.expect(',')?;
+	let symtab = cursor.string()?;
+	cursor.skip_whitespace();
+
+	if cursor.rest().starts_with(".split(") {
+		cursor.position += ".split(".len();
+		let separator = cursor.string()?;
+		cursor.expect(')')?;
+		if separator != "|" {
+			return Err(Error::Malformed("unexpected symbol separator"));
+		}
+	}
+
+	// Skip the remaining `, 0, {}))` arguments up to the closing parenthesis of `eval(`.
+	let mut depth = 2;
+	while depth > 0 {
+		match cursor.next() {
+			Some('(') => depth += 1,
+			Some(')') => depth -= 1,
+			Some(quote @ ('\'' | '"')) => {
+				cursor.position -= quote.len_utf8();
+				cursor.string()?;
+			}
+			Some(_) => (),
+			None => return Err(Error::Malformed("unterminated eval")),
+		}
+	}
+
+	// Radixes above 62 encode symbols as punctuation that `\w+` can't match, so they're rejected outright.
+	if !(2..=62).contains(&radix) {
+		return Err(Error::InvalidRadix(radix));
+	}
+
+	Ok(Packed {
+		start,
+		end: cursor.position,
+		payload,
+		radix,
+		count,
+		symtab: symtab.split('|').map(str::to_string).collect(),
+	})
+}
+
+impl Packed {
+	fn unpack(&self) -> String {
+		lazy_static! {
+			static ref WORD: Regex = Regex::new(r"\b\w+\b").unwrap();
+		}
+
+		// Same lookup table the packer's `d[e(c)] = k[c] || e(c)` loop builds, applied in one pass.
+		// `count` comes from the script, so it's capped at the symbols that actually exist.
+		let dictionary: HashMap<String, &str> = (0..self.count.min(self.symtab.len()))
+			.filter_map(|index| {
+				let symbol = self.symtab.get(index)?;
+				(!symbol.is_empty()).then(|| (encode(index, self.radix), symbol.as_str()))
+			})
+			.collect();
+
+		let unpacked = WORD.replace_all(&self.payload, |captures: &Captures| {
+			let word = &captures[0];
+			dictionary.get(word).copied().unwrap_or(word).to_string()
+		});
+
+		unpacked.into_owned()
+	}
+}
+
+// `e=function(c){return(c<a?'':e(parseInt(c/a)))+((c=c%a)>35?String.fromCharCode(c+29):c.toString(36))}`
+fn encode(value: usize, radix: u32) -> String {
+	let radix = radix as usize;
+	let mut digits = Vec::new();
+	let mut value = value;
+	loop {
+		let digit = (value % radix) as u32;
+		digits.push(if digit > 35 {
+			char::from_u32(digit + 29).unwrap_or('\0')
+		} else {
+			char::from_digit(digit, 36).unwrap()
+		});
+		value /= radix;
+		if value == 0 {
+			break;
+		}
+	}
+
+	digits.iter().rev().collect()
+}
+
+pub(super) struct Cursor<'a> {
+	source: &'a str,
+	position: usize,
+}
+
+impl<'a> Cursor<'a> {
+	pub(super) fn new(source: &'a str, position: usize) -> Self {
+		Cursor { source, position }
+	}
+
+	pub(super) fn position(&self) -> usize {
+		self.position
+	}
+
+	fn rest(&self) -> &'a str {
+		&self.source[self.position..]
+	}
+
+	pub(super) fn peek(&self) -> Option<char> {
+		self.rest().chars().next()
+	}
+
+	pub(super) fn next(&mut self) -> Option<char> {
+		let c = self.peek()?;
+		self.position += c.len_utf8();
+		Some(c)
+	}
+
+	pub(super) fn identifier(&mut self) -> &'a str {
+		let rest = self.rest();
+		let len = rest
+			.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+			.unwrap_or(rest.len());
+		self.position += len;
+		&rest[..len]
+	}
+
+	pub(super) fn skip_whitespace(&mut self) {
+		while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+			self.position += c.len_utf8();
+		}
+	}
+
+	fn expect(&mut self, expected: char) -> Result<(), Error> {
+		self.skip_whitespace();
+		match self.next() {
+			Some(c) if c == expected => Ok(()),
+			_ => Err(Error::Malformed("unexpected token in arguments")),
+		}
+	}
+
+	fn integer(&mut self) -> Result<usize, Error> {
+		self.skip_whitespace();
+		let digits = self
+			.rest()
+			.find(|c: char| !c.is_ascii_digit())
+			.unwrap_or(self.rest().len());
+		let value = self.rest()[..digits]
+			.parse()
+			.map_err(|_| Error::Malformed("expected an integer"))?;
+		self.position += digits;
+		Ok(value)
+	}
+
+	pub(super) fn string(&mut self) -> Result<String, Error> {
+		self.skip_whitespace();
+		let quote = match self.next() {
+			Some(quote @ ('\'' | '"')) => quote,
+			_ => return Err(Error::Malformed("expected a string literal")),
+		};
+
+		let mut value = String::new();
+		loop {
+			match self.next().ok_or(Error::Malformed("unterminated string"))? {
+				c if c == quote => return Ok(value),
+				'\\' => match self.next().ok_or(Error::Malformed("unterminated string"))? {
+					'n' => value.push('\n'),
+					'r' => value.push('\r'),
+					't' => value.push('\t'),
+					'b' => value.push('\u{8}'),
+					'f' => value.push('\u{c}'),
+					'v' => value.push('\u{b}'),
+					'0' => value.push('\0'),
+					'x' => value.push(self.hex(2)?),
+					'u' => value.push(self.hex(4)?),
+					'\n' => (),
+					c => value.push(c),
+				},
+				c => value.push(c),
+			}
+		}
+	}
+
+	fn hex(&mut self, len: usize) -> Result<char, Error> {
+		let digits = self
+			.rest()
+			.get(..len)
+			.ok_or(Error::Malformed("truncated escape sequence"))?;
+		let c = u32::from_str_radix(digits, 16)
+			.ok()
+			.and_then(char::from_u32)
+			.ok_or(Error::Malformed("invalid escape sequence"))?;
+		self.position += len;
+		Ok(c)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_encode() {
+		assert_eq!(encode(0, 36), "0");
+		assert_eq!(encode(35, 36), "z");
+		assert_eq!(encode(36, 36), "10");
+		assert_eq!(encode(36, 62), "A");
+		assert_eq!(encode(61, 62), "Z");
+		assert_eq!(encode(62, 62), "10");
+	}
+
+	#[test]
+	fn test_unpack_single_pass() {
+		// Sequential replacement would turn `l` into `e` and then `e` into `zt9ph5w6n5cj_o`.
+		let packed = r"eval(function(p,a,c,k,e,d){return p}('e/l',36,22,'||||||||||||||zt9ph5w6n5cj_o|||||||e'.split('|'),0,{}))";
+		assert_eq!(unpack(packed).unwrap(), "zt9ph5w6n5cj_o/e");
+	}
+
+	#[test]
+	fn test_unpack_escaped_quotes() {
+		let packed = r#"eval(function(p,a,c,k,e,d){return p}('0(\'1\\\'s \"2\"\')',10,3,'alert|it|quoted'.split('|'),0,{}))"#;
+		assert_eq!(unpack(packed).unwrap(), r#"alert('it\'s "quoted"')"#);
+	}
+
+	#[test]
+	fn test_unpack_count_exceeds_symtab() {
+		let packed = "eval(function(p,a,c,k,e,d){return p}('0 1 2',10,5,'a|b'.split('|'),0,{}))";
+		assert_eq!(unpack(packed).unwrap(), "a b 2");
+
+		let packed = "eval(function(p,a,c,k,e,d){return p}('0 1 2',10,9000000000000000000,'a|b'.split('|'),0,{}))";
+		assert_eq!(unpack(packed).unwrap(), "a b 2");
+	}
+
+	#[test]
+	fn test_unpack_radix_above_36() {
+		let symtab = (0..64)
+			.map(|i| format!("w{i}"))
+			.collect::<Vec<_>>()
+			.join("|");
+		let packed = format!(
+			"eval(function(p,a,c,k,e,d){{return p}}('A Z 10 11',62,64,'{symtab}'.split('|'),0,{{}}))"
+		);
+		assert_eq!(unpack(&packed).unwrap(), "w36 w61 w62 w63");
+	}
+
+	#[test]
+	fn test_unpack_multiple_blocks() {
+		let source = "var a=1;eval(function(p,a,c,k,e,d){return p}('0',10,1,'first'.split('|'),0,{}))\nvar b=2;eval(function(p,a,c,k,e,r){return p}(\"0\",10,1,\"second\".split(\"|\"),0,{}))";
+		assert_eq!(unpack(source).unwrap(), "var a=1;first\nvar b=2;second");
+		assert_eq!(unpack_all(source).unwrap(), vec!["first", "second"]);
+	}
+
+	#[test]
+	fn test_unpack_nested() {
+		let inner =
+			r"eval(function(p,a,c,k,e,d){return p}(\'0\',10,1,\'inner\'.split(\'|\'),0,{}))";
+		let packed =
+			format!("eval(function(p,a,c,k,e,d){{return p}}('{inner}',10,0,''.split('|'),0,{{}}))");
+		assert_eq!(unpack(&packed).unwrap(), "inner");
+	}
+
+	#[test]
+	fn test_unpack_errors() {
+		assert!(matches!(unpack("var a = 1;"), Err(Error::NotPacked)));
+		assert!(matches!(
+			unpack("eval(function(p,a,c,k,e,d){return p}('0',1,1,'a'.split('|'),0,{}))"),
+			Err(Error::InvalidRadix(1))
+		));
+		assert!(matches!(
+			unpack("eval(function(p,a,c,k,e,d){return p}('_',95,67,'a'.split('|'),0,{}))"),
+			Err(Error::InvalidRadix(95))
+		));
+		// 4294967332 would wrap to 36 if it were truncated.
+		assert!(matches!(
+			unpack("eval(function(p,a,c,k,e,d){return p}('0',4294967332,1,'a'.split('|'),0,{}))"),
+			Err(Error::Malformed("radix out of range"))
+		));
+		assert!(matches!(
+			unpack("eval(function(p,a,c,k,e,d){return p}('0,10,1"),
+			Err(Error::Malformed(_))
+		));
+	}
+}
@@ -0,0 +1,310 @@
+use std::{
+	fmt,
+	future::Future,
+	time::{Duration, Instant},
+};
+
+use reqwest::{header, StatusCode};
+use serde::Serialize;
+
+use crate::{episodes, get_source, search, servers, MissingFeature, Provider, Source};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Stage {
+	Search,
+	Episodes,
+	Servers,
+	Source,
+	Playlist,
+}
+
+impl fmt::Display for Stage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Stage::Search => write!(f, "search"),
+			Stage::Episodes => write!(f, "episodes"),
+			Stage::Servers => write!(f, "servers"),
+			Stage::Source => write!(f, "source"),
+			Stage::Playlist => write!(f, "playlist"),
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct StageReport {
+	pub stage: Stage,
+	pub detail: String,
+	pub error: Option<String>,
+	// Scheme whose keys stopped decrypting, when that is what broke the stage.
+	pub keys_outdated: Option<&'static str>,
+	// Cargo feature the stage needs, when the build rather than the site is what broke it.
+	pub missing_feature: Option<&'static str>,
+	pub latency_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+	pub provider: Provider,
+	pub stages: Vec<StageReport>,
+	// Time taken by every stage that ran, so a slow provider shows up before it times out.
+	pub latency_ms: u64,
+}
+
+impl Report {
+	pub fn is_ok(&self) -> bool {
+		self.failed_stage().is_none()
+	}
+
+	pub fn failed_stage(&self) -> Option<&StageReport> {
+		self.stages.iter().find(|stage| stage.error.is_some())
+	}
+
+	async fn run<T>(
+		&mut self, stage: Stage, future: impl Future<Output = Result<T, anyhow::Error>>,
+		detail: impl FnOnce(&T) -> String,
+	) -> Option<T> {
+		let started = Instant::now();
+		let result = future.await;
+		let latency_ms = millis(started.elapsed());
+		let (detail, error, keys_outdated, missing_feature) = match &result {
+			Ok(value) => (detail(value), None, None, None),
+			Err(error) => (
+				String::new(),
+				Some(format!("{error:#}")),
+				keys_outdated(error),
+				missing_feature(error),
+			),
+		};
+
+		self.stages.push(StageReport {
+			stage,
+			detail,
+			error,
+			keys_outdated,
+			missing_feature,
+			latency_ms,
+		});
+		self.latency_ms += latency_ms;
+		result.ok()
+	}
+}
+
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "{} ({}ms)", self.provider, self.latency_ms)?;
+		for stage in &self.stages {
+			let latency = stage.latency_ms;
+			match (&stage.error, stage.keys_outdated, stage.missing_feature) {
+				(Some(_), _, Some(feature)) => writeln!(
+					f,
+					"  {}: config error: built without the `{feature}` feature ({latency}ms)",
+					stage.stage
+				)?,
+				(Some(_), Some(scheme), None) => writeln!(
+					f,
+					"  {}: {scheme} keys are outdated ({latency}ms)",
+					stage.stage
+				)?,
+				(Some(error), None, None) => {
+					writeln!(f, "  {}: failed: {error} ({latency}ms)", stage.stage)?
+				}
+				(None, ..) => writeln!(f, "  {}: ok ({}, {latency}ms)", stage.stage, stage.detail)?,
+			}
+		}
+		Ok(())
+	}
+}
+
+// Runs search -> episodes -> servers -> source -> playlist with the first hit at each stage and stops at the first failure.
+pub async fn diagnose(provider: &Provider, query: &str) -> Report {
+	let mut report = Report {
+		provider: *provider,
+		stages: Vec::new(),
+		latency_ms: 0,
+	};
+
+	let results = async { non_empty(search(provider, query).await?) };
+	let Some(results) = report
+		.run(Stage::Search, results, |results| {
+			format!("{} results, using {}", results.len(), results[0].title)
+		})
+		.await
+	else {
+		return report;
+	};
+
+	let episodes = async { non_empty(episodes(&results[0].id).await?) };
+	let Some(episodes) = report
+		.run(Stage::Episodes, episodes, |episodes| {
+			format!("{} episodes", episodes.len())
+		})
+		.await
+	else {
+		return report;
+	};
+
+	let servers = async { non_empty(servers(&episodes[0].id).await?) };
+	let Some(servers) = report
+		.run(Stage::Servers, servers, |servers| {
+			format!("{} servers", servers.len())
+		})
+		.await
+	else {
+		return report;
+	};
+
+	// A single dead mirror is normal, the stage only fails when no server resolves.
+	let source = async {
+		let mut source = Err(anyhow::anyhow!("No servers"));
+		for server in &servers {
+			source = get_source(provider, &server.url)
+				.await
+				.map(|source| (server.name.clone(), source));
+			if source.is_ok() {
+				break;
+			}
+		}
+		source
+	};
+	let Some((_, source)) = report
+		.run(Stage::Source, source, |(name, source)| {
+			format!("{name}: {}", source.url)
+		})
+		.await
+	else {
+		return report;
+	};
+
+	report
+		.run(Stage::Playlist, playlist(&source), |status| {
+			status.to_string()
+		})
+		.await;
+
+	report
+}
+
+// HEADs the playlist with the headers players send, which is where expired tokens and dead CDNs show up.
+async fn playlist(source: &Source) -> Result<StatusCode, anyhow::Error> {
+	let client = reqwest::Client::new();
+	let mut status = probe(&client, source, reqwest::Method::HEAD).await?;
+	// Some CDNs only serve GET.
+	if status == StatusCode::METHOD_NOT_ALLOWED {
+		status = probe(&client, source, reqwest::Method::GET).await?;
+	}
+	anyhow::ensure!(status.is_success(), "{} returned {status}", source.url);
+	Ok(status)
+}
+
+async fn probe(
+	client: &reqwest::Client, source: &Source, method: reqwest::Method,
+) -> Result<StatusCode, anyhow::Error> {
+	let mut request = client
+		.request(method, &source.url)
+		.header(header::RANGE, "bytes=0-0");
+	for (name, value) in &source.headers {
+		request = request.header(name, value);
+	}
+	Ok(request.send().await?.status())
+}
+
+pub async fn diagnose_all(query: &str) -> Vec<Report> {
+	let mut reports = Vec::new();
+	for provider in Provider::ALL {
+		reports.push(diagnose(provider, query).await);
+	}
+	reports
+}
+
+fn non_empty<T>(items: Vec<T>) -> Result<Vec<T>, anyhow::Error> {
+	anyhow::ensure!(!items.is_empty(), "Nothing found");
+	Ok(items)
+}
+
+fn millis(duration: Duration) -> u64 {
+	u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+fn keys_outdated(error: &anyhow::Error) -> Option<&'static str> {
+	error.chain().find_map(
+		|cause| match cause.downcast_ref::<protozoa_cryptography::Error>()? {
+			protozoa_cryptography::Error::KeysOutdated { scheme, .. } => Some(*scheme),
+			_ => None,
+		},
+	)
+}
+
+fn missing_feature(error: &anyhow::Error) -> Option<&'static str> {
+	error
+		.chain()
+		.find_map(|cause| cause.downcast_ref::<MissingFeature>())
+		.map(|missing| missing.feature)
+}
+
+#[cfg(test)]
+mod tests {
+	use anyhow::Context as _;
+
+	use super::*;
+
+	#[test]
+	fn test_keys_outdated() {
+		let error = Err::<(), _>(protozoa_cryptography::Error::KeysOutdated {
+			scheme: "megaup",
+			source: None,
+		})
+		.context("Failed to decrypt source")
+		.unwrap_err();
+		assert_eq!(keys_outdated(&error), Some("megaup"));
+		assert_eq!(keys_outdated(&anyhow::anyhow!("No result")), None);
+	}
+
+	#[test]
+	fn test_missing_feature() {
+		let error = anyhow::Error::from(MissingFeature {
+			feature: "extractor-megacloud",
+			what: "MegaCloud sources",
+		})
+		.context("Failed to get source");
+		assert_eq!(missing_feature(&error), Some("extractor-megacloud"));
+		assert_eq!(missing_feature(&anyhow::anyhow!("No result")), None);
+	}
+
+	#[cfg(feature = "hianime")]
+	#[tokio::test]
+	async fn test_run() {
+		let mut report = Report {
+			provider: Provider::HiAnime,
+			stages: Vec::new(),
+			latency_ms: 0,
+		};
+
+		let episodes = report
+			.run(Stage::Episodes, async { Ok(vec![1, 2]) }, |episodes| {
+				format!("{} episodes", episodes.len())
+			})
+			.await;
+		assert_eq!(episodes, Some(vec![1, 2]));
+		let source = report
+			.run(
+				Stage::Source,
+				async {
+					tokio::time::sleep(Duration::from_millis(20)).await;
+					Err::<(), _>(anyhow::anyhow!("No servers"))
+				},
+				|_| String::new(),
+			)
+			.await;
+		assert_eq!(source, None);
+
+		assert!(!report.is_ok());
+		let failed = report.failed_stage().unwrap();
+		assert_eq!(failed.stage, Stage::Source);
+		assert!(failed.latency_ms >= 20);
+		assert_eq!(
+			report.latency_ms,
+			report.stages[0].latency_ms + failed.latency_ms
+		);
+		assert!(report.to_string().contains("source: failed: No servers"));
+	}
+}
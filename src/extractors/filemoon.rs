@@ -0,0 +1,72 @@
+use anyhow::Context as _;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::Source;
+
+pub const DOMAINS: &[&str] = &[
+	"filemoon.sx",
+	"filemoon.to",
+	"filemoon.in",
+	"filemoon.nl",
+	"filemoon.link",
+	"kerapoxy.cc",
+];
+
+pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
+	let mut url = url.replace("/d/", "/e/");
+	let mut html = super::fetch(&url, None).await?;
+
+	// The embed page is sometimes only a wrapper around the actual player iframe.
+	if !html.contains("eval(function(p,a,c,k,e,d)") {
+		let iframe = parse_iframe(&html)?;
+		html = super::fetch(&iframe, Some(&format!("{}/", super::origin(&url)?))).await?;
+		url = iframe;
+	}
+
+	let (source, captions) = super::parse_packed_player(&html)?;
+
+	Ok(Source {
+		url: source,
+		captions,
+		headers: super::referer_headers(&url)?,
+	})
+}
+
+fn parse_iframe(html: &str) -> Result<String, anyhow::Error> {
+	lazy_static! {
+		static ref IFRAME: Regex = Regex::new(r#"<iframe[^>]+src="([^"]+)""#).unwrap();
+	}
+
+	let captures = IFRAME
+		.captures(html)
+		.context("Failed to find player iframe")?;
+	Ok(captures[1].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const FIXTURE: &str = r#"<script data-cfasync="false" type="text/javascript">eval(function(p,a,c,k,e,d){e=function(c){return(c<a?'':e(parseInt(c/a)))+((c=c%a)>35?String.fromCharCode(c+29):c.toString(36))};if(!''.replace(/^/,String)){while(c--){d[e(c)]=k[c]||e(c)}k=[function(e){return d[e]}];e=function(){return'\\w+'};c=1};while(c--){if(k[c]){p=p.replace(new RegExp('\\b'+e(c)+'\\b','g'),k[c])}}return p}('0("1").2({3:[{4:"5://6.7.8.9.a/b/c/d/e/f.g?h=i&j=k&l=m"}],n:"5://o.p.q/r.s",t:[{4:"5://o.p.q/u.v",w:"",x:"y"}]});',36,35,'jwplayer|vplayer|setup|sources|file|https|be6721|rcr72|waw04|i8yz83pn|com|hls2|03|00391|zt9ph5w6n5cj_o|master|m3u8|t|hL3m0dXQ|s|1700000000|sp|10800|image|img|filemoon|sx|zt9ph5w6n5cj|jpg|tracks|zt9ph5w6n5cj0000|vtt|label|kind|thumbnails'.split('|'),0,{}))
+</script>"#;
+
+	#[test]
+	fn test_parse_packed_player() {
+		let (url, captions) = super::super::parse_packed_player(FIXTURE).unwrap();
+		assert_eq!(
+			url,
+			"https://be6721.rcr72.waw04.i8yz83pn.com/hls2/03/00391/zt9ph5w6n5cj_o/master.m3u8?t=hL3m0dXQ&s=1700000000&sp=10800"
+		);
+		assert!(captions.is_empty(), "Thumbnail tracks should be dropped");
+	}
+
+	#[test]
+	fn test_parse_iframe() {
+		let html = r#"<div class="player"><iframe src="https://kerapoxy.cc/bkg/zt9ph5w6n5cj" frameborder="0" allowfullscreen></iframe></div>"#;
+		assert_eq!(
+			parse_iframe(html).unwrap(),
+			"https://kerapoxy.cc/bkg/zt9ph5w6n5cj"
+		);
+	}
+}
@@ -0,0 +1,137 @@
+pub mod doodstream;
+pub mod filemoon;
+pub mod mp4upload;
+pub mod streamtape;
+pub mod streamwish;
+
+use std::{collections::BTreeMap, time::Instant};
+
+use anyhow::Context as _;
+use reqwest::{header, Client, Url};
+
+use crate::{deobfuscate, telemetry, Caption, Source};
+
+const USER_AGENT: &str =
+	"Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36";
+
+pub fn is_supported(url: &str) -> bool {
+	host(url).is_some_and(|host| {
+		[
+			streamtape::DOMAINS,
+			filemoon::DOMAINS,
+			mp4upload::DOMAINS,
+			streamwish::DOMAINS,
+			doodstream::DOMAINS,
+		]
+		.iter()
+		.any(|domains| matches_host(&host, domains))
+	})
+}
+
+#[tracing::instrument(skip_all, fields(url = %telemetry::redact(url)), err(level = "debug"))]
+pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
+	let host = host(url).context("Invalid url")?;
+
+	if matches_host(&host, streamtape::DOMAINS) {
+		streamtape::get_source(url).await
+	} else if matches_host(&host, filemoon::DOMAINS) {
+		filemoon::get_source(url).await
+	} else if matches_host(&host, mp4upload::DOMAINS) {
+		mp4upload::get_source(url).await
+	} else if matches_host(&host, streamwish::DOMAINS) {
+		streamwish::get_source(url).await
+	} else if matches_host(&host, doodstream::DOMAINS) {
+		doodstream::get_source(url).await
+	} else {
+		anyhow::bail!("Unsupported host: {host}")
+	}
+}
+
+fn host(url: &str) -> Option<String> {
+	Url::parse(url).ok()?.host_str().map(str::to_lowercase)
+}
+
+fn matches_host(host: &str, domains: &[&str]) -> bool {
+	domains.iter().any(|domain| {
+		host == *domain
+			|| host
+				.strip_suffix(domain)
+				.is_some_and(|sub| sub.ends_with('.'))
+	})
+}
+
+pub(crate) fn origin(url: &str) -> Result<String, anyhow::Error> {
+	let url = Url::parse(url)?;
+	Ok(url.origin().ascii_serialization())
+}
+
+pub(crate) fn referer_headers(url: &str) -> Result<BTreeMap<String, String>, anyhow::Error> {
+	let origin = origin(url)?;
+	Ok(BTreeMap::from([
+		(header::REFERER.to_string(), format!("{origin}/")),
+		(header::ORIGIN.to_string(), origin),
+		(header::USER_AGENT.to_string(), USER_AGENT.to_string()),
+	]))
+}
+
+#[tracing::instrument(
+	skip_all,
+	fields(url = %telemetry::redact(url), status = tracing::field::Empty)
+)]
+async fn fetch(url: &str, referer: Option<&str>) -> Result<String, anyhow::Error> {
+	let client = Client::builder().user_agent(USER_AGENT).build()?;
+	let mut request = client.get(url);
+	if let Some(referer) = referer {
+		request = request.header(header::REFERER, referer);
+	}
+
+	let started = Instant::now();
+	let response = request.send().await?;
+	let status = response.status();
+	let text = response.text().await?;
+	telemetry::response(url, status, started.elapsed(), &text);
+	anyhow::ensure!(status.is_success(), "{url} returned {status}");
+	Ok(text)
+}
+
+// Filemoon and StreamWish both ship a packed JW Player setup script.
+fn parse_packed_player(html: &str) -> Result<(String, Vec<Caption>), anyhow::Error> {
+	let unpacked = deobfuscate::unpack(html).context("Failed to unpack source")?;
+
+	let sources = deobfuscate::find_json(&unpacked, "sources").context("Failed to get sources")?;
+	let url = sources[0]["file"]
+		.as_str()
+		.context("Failed to get source")?
+		.to_string();
+
+	let mut captions: Vec<Caption> = match deobfuscate::find_json(&unpacked, "tracks") {
+		Some(tracks) => serde_json::from_value(tracks)?,
+		None => Vec::new(),
+	};
+	captions.retain(|caption| caption.kind != "thumbnails");
+
+	Ok((url, captions))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_is_supported() {
+		assert!(is_supported("https://streamtape.com/e/KDMxrGyDdXSDO0"));
+		assert!(is_supported(
+			"https://www.mp4upload.com/embed-7l2kz0f9jq3x.html"
+		));
+		assert!(is_supported("https://dood.wf/e/xyz123"));
+		assert!(!is_supported("https://megacloud.tv/embed-2/e-1/abc"));
+		assert!(!is_supported("https://notstreamtape.com/e/abc"));
+	}
+
+	#[test]
+	fn test_referer_headers() {
+		let headers = referer_headers("https://filemoon.sx/e/abc/video").unwrap();
+		assert_eq!(headers["referer"], "https://filemoon.sx/");
+		assert_eq!(headers["origin"], "https://filemoon.sx");
+	}
+}
@@ -0,0 +1,52 @@
+use anyhow::Context as _;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::Source;
+
+pub const DOMAINS: &[&str] = &["mp4upload.com"];
+
+pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
+	let html = super::fetch(url, Some("https://www.mp4upload.com/")).await?;
+
+	Ok(Source {
+		url: parse(&html)?,
+		captions: Vec::new(),
+		headers: super::referer_headers("https://www.mp4upload.com/")?,
+	})
+}
+
+fn parse(html: &str) -> Result<String, anyhow::Error> {
+	lazy_static! {
+		static ref PLAYER_SRC: Regex =
+			Regex::new(r#"player\.src\(\s*\{[^}]*?src:\s*"([^"]+)""#).unwrap();
+	}
+
+	let captures = PLAYER_SRC
+		.captures(html)
+		.context("Failed to find player source")?;
+
+	Ok(captures[1].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const FIXTURE: &str = r#"<script>
+var player = videojs('player', { controls: true, preload: 'none' });
+player.src({
+	type: "video/mp4",
+	src: "https://a4.mp4upload.com:183/d/xkxz5zmbz3b4quuoxgqbz5tkh6yfd3xmn5zvzqpnurgo6aq7uhlw2s5y/video.mp4"
+});
+player.poster("https://a4.mp4upload.com/i/01234/7l2kz0f9jq3x.jpg");
+</script>"#;
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(
+			parse(FIXTURE).unwrap(),
+			"https://a4.mp4upload.com:183/d/xkxz5zmbz3b4quuoxgqbz5tkh6yfd3xmn5zvzqpnurgo6aq7uhlw2s5y/video.mp4"
+		);
+	}
+}
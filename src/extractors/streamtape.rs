@@ -0,0 +1,67 @@
+use anyhow::Context as _;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::Source;
+
+pub const DOMAINS: &[&str] = &[
+	"streamtape.com",
+	"streamtape.net",
+	"streamtape.to",
+	"streamta.pe",
+	"strtape.cloud",
+	"strtpe.link",
+	"stape.fun",
+];
+
+pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
+	let url = url.replace("/v/", "/e/");
+	let html = super::fetch(&url, None).await?;
+
+	Ok(Source {
+		url: parse(&html)?,
+		captions: Vec::new(),
+		headers: super::referer_headers(&url)?,
+	})
+}
+
+// The page assembles the link as `'//host/get_video?...&token=' + ('xcd' + token).substring(1).substring(2)`.
+fn parse(html: &str) -> Result<String, anyhow::Error> {
+	lazy_static! {
+		static ref ROBOTLINK: Regex = Regex::new(
+			r"getElementById\('robotlink'\)\.innerHTML\s*=\s*'([^']+)'\s*\+\s*\('([^']+)'\)((?:\.substring\(\d+\))*)"
+		)
+		.unwrap();
+		static ref SUBSTRING: Regex = Regex::new(r"\.substring\((\d+)\)").unwrap();
+	}
+
+	let captures = ROBOTLINK
+		.captures(html)
+		.context("Failed to find robotlink")?;
+
+	let mut token = &captures[2];
+	for substring in SUBSTRING.captures_iter(&captures[3]) {
+		let start: usize = substring[1].parse()?;
+		token = token.get(start..).context("Invalid substring")?;
+	}
+
+	let path = captures[1].trim_start_matches('/');
+	Ok(format!("https://{path}{token}&stream=1"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const FIXTURE: &str = r#"<div id="ideoolink" style="display:none;">/streamtape.com/get_video?id=KDMxrGyDdXSDO0&expires=1700000000&ip=F0ZkDRSQKxSHDN&token=jWmi_4</div>
+<script>document.getElementById('ideoolink').innerHTML = "/streamtape.com/get_video?id=KDMxrGyDdXSDO0&expires=1700000000&ip=F0ZkDRSQKxSHDN&token=jWmi_4";
+document.getElementById('robotlink').innerHTML = '//streamtape.com/get_video?id=KDMxrGyDdXSDO0&expires=1700000000&ip=F0ZkDRSQKxSHDN&token=jWmi_4' + ('xcd3Zq2a5_aD8W').substring(1).substring(2);</script>"#;
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(
+			parse(FIXTURE).unwrap(),
+			"https://streamtape.com/get_video?id=KDMxrGyDdXSDO0&expires=1700000000&ip=F0ZkDRSQKxSHDN&token=jWmi_43Zq2a5_aD8W&stream=1"
+		);
+	}
+}
@@ -0,0 +1,56 @@
+use crate::Source;
+
+pub const DOMAINS: &[&str] = &[
+	"streamwish.com",
+	"streamwish.to",
+	"swdyu.com",
+	"wishembed.pro",
+	"awish.pro",
+	"dwish.pro",
+	"mwish.pro",
+	"vidstreamnew.xyz",
+];
+
+pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
+	let url = url.replace("/f/", "/e/");
+	let html = super::fetch(&url, None).await?;
+	let (source, captions) = super::parse_packed_player(&html)?;
+
+	Ok(Source {
+		url: source,
+		captions,
+		headers: super::referer_headers(&url)?,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::Caption;
+
+	const FIXTURE: &str = r#"<script type='text/javascript'>eval(function(p,a,c,k,e,d){e=function(c){return(c<a?'':e(parseInt(c/a)))+((c=c%a)>35?String.fromCharCode(c+29):c.toString(36))};if(!''.replace(/^/,String)){while(c--){d[e(c)]=k[c]||e(c)}k=[function(e){return d[e]}];e=function(){return'\\w+'};c=1};while(c--){if(k[c]){p=p.replace(new RegExp('\\b'+e(c)+'\\b','g'),k[c])}}return p}('0 1={"2":"3://4.5.6/2/7/8/9/a.b?c=d&e=f"};g("h").i({j:[{k:"3://4.5.6/2/7/8/9/a.b?c=d&e=f",l:"m"}],n:[{k:"3://4.5.6/o/7/8/p.o",q:"r",s:"t"},{k:"3://4.5.6/o/7/8/u.o",q:"v",s:"t"}]});',62,32,'var|links|hls2|https|str|swdyu|com|01|00076|fk3b2rbk6zmk_n|master|m3u8|t|Qb9kq|s|1700000000|jwplayer|vplayer|setup|sources|file|type|hls|tracks|vtt|fk3b2rbk6zmk_eng|label|English|kind|captions|fk3b2rbk6zmk_spa|Spanish'.split('|'),0,{}))
+</script>"#;
+
+	#[test]
+	fn test_parse_packed_player() {
+		let (url, captions) = super::super::parse_packed_player(FIXTURE).unwrap();
+		assert_eq!(
+			url,
+			"https://str.swdyu.com/hls2/01/00076/fk3b2rbk6zmk_n/master.m3u8?t=Qb9kq&s=1700000000"
+		);
+		assert_eq!(
+			captions,
+			vec![
+				Caption {
+					url: "https://str.swdyu.com/vtt/01/00076/fk3b2rbk6zmk_eng.vtt".to_string(),
+					label: Some("English".to_string()),
+					kind: "captions".to_string(),
+				},
+				Caption {
+					url: "https://str.swdyu.com/vtt/01/00076/fk3b2rbk6zmk_spa.vtt".to_string(),
+					label: Some("Spanish".to_string()),
+					kind: "captions".to_string(),
+				},
+			]
+		);
+	}
+}
@@ -1,14 +1,16 @@
-use crate::{Caption, Episode, Locale, SearchResult, Server, Source};
+use crate::{
+	extractors, format_label,
+	mirrors::{self, Site},
+	AnimeId, Caption, Episode, EpisodeId, Locale, MissingFeature, Provider, Related, Relation,
+	SearchResult, Server, ServerId, Source,
+};
 use anyhow::Context as _;
 use kuchikiki::traits::*;
 use protozoa_cryptography::sources::megacloud;
 use serde_json::Value;
 
 pub async fn search(query: &str) -> Result<Vec<SearchResult>, anyhow::Error> {
-	let html = reqwest::get(format!("https://hianime.to/search?keyword={query}"))
-		.await?
-		.text()
-		.await?;
+	let html = mirrors::text(Site::HiAnime, &format!("/search?keyword={query}")).await?;
 
 	let document = kuchikiki::parse_html().one(html);
 	let items = document
@@ -30,7 +32,7 @@ pub async fn search(query: &str) -> Result<Vec<SearchResult>, anyhow::Error> {
 			SearchResult {
 				title: title.to_string(),
 				poster: poster.to_string(),
-				id: id.to_string(),
+				id: AnimeId::new(Provider::HiAnime, id),
 			}
 		})
 		.collect();
@@ -38,77 +40,239 @@ pub async fn search(query: &str) -> Result<Vec<SearchResult>, anyhow::Error> {
 	Ok(results)
 }
 
+pub async fn related(id: &str) -> Result<Vec<Related>, anyhow::Error> {
+	// Detail pages are addressed by slug, which the tooltip's watch link carries.
+	let json: Value = mirrors::json(Site::HiAnime, &format!("/ajax/movie/qtip/{id}")).await?;
+
+	let href = {
+		let html = json["html"].as_str().context("No tooltip")?;
+		let document = kuchikiki::parse_html().one(html);
+		let watch = document
+			.select_first("a.btn-play")
+			.map_err(|_| anyhow::anyhow!("No watch link"))?;
+		let attributes = watch.attributes.borrow();
+		attributes.get("href").context("No watch link")?.to_string()
+	};
+
+	let slug = href.trim_start_matches("/watch");
+	let html = mirrors::text(Site::HiAnime, slug).await?;
+
+	parse_related(&html, id)
+}
+
+fn parse_related(html: &str, id: &str) -> Result<Vec<Related>, anyhow::Error> {
+	let document = kuchikiki::parse_html().one(html);
+	let mut related = Vec::new();
+
+	let seasons = document
+		.select(".os-list a.os-item")
+		.map_err(|_| anyhow::anyhow!("Failed to select seasons"))?;
+	for season in seasons {
+		let attributes = season.attributes.borrow();
+		let key = key(attributes.get("href").context("No season link")?);
+		let title = attributes.get("title").context("No season title")?;
+		// Posters are only set as an inline background image.
+		let poster = season
+			.as_node()
+			.select_first(".season-poster")
+			.ok()
+			.and_then(|poster| {
+				let style = poster.attributes.borrow().get("style")?.to_string();
+				let url = style.split_once("url(")?.1.split_once(')')?.0;
+				Some(url.trim_matches(['\'', '"']).to_string())
+			});
+
+		related.push(Related {
+			title: title.to_string(),
+			poster,
+			relation: Relation::Season,
+			format: None,
+			id: AnimeId::new(Provider::HiAnime, key),
+		});
+	}
+
+	// The sidebar has no relation labels, so only movies get a relation from their format.
+	let blocks = document
+		.select(".block_area_sidebar")
+		.map_err(|_| anyhow::anyhow!("Failed to select sidebar"))?;
+	for block in blocks {
+		let heading = block
+			.as_node()
+			.select_first(".cat-heading")
+			.map(|heading| heading.text_contents())
+			.unwrap_or_default();
+		if !heading.contains("Related") {
+			continue;
+		}
+
+		let items = block
+			.as_node()
+			.select("li")
+			.map_err(|_| anyhow::anyhow!("Failed to select related"))?;
+		for item in items {
+			let Ok(name) = item.as_node().select_first(".film-name a") else {
+				continue;
+			};
+			let attributes = name.attributes.borrow();
+			let key = key(attributes.get("href").context("No related link")?);
+			let title = attributes
+				.get("title")
+				.map(str::to_string)
+				.unwrap_or_else(|| name.text_contents());
+			let poster = item
+				.as_node()
+				.select_first(".film-poster-img")
+				.ok()
+				.and_then(|img| img.attributes.borrow().get("data-src").map(str::to_string));
+			let tick = item
+				.as_node()
+				.select_first(".tick")
+				.map(|tick| tick.text_contents())
+				.unwrap_or_default();
+			let format = tick.split_whitespace().find_map(format_label);
+			let relation = match format.as_deref() {
+				Some("Movie") => Relation::Movie,
+				_ => Relation::Other,
+			};
+
+			related.push(Related {
+				title: title.trim().to_string(),
+				poster,
+				relation,
+				format,
+				id: AnimeId::new(Provider::HiAnime, key),
+			});
+		}
+	}
+
+	related.retain(|entry| entry.id.key != id);
+	Ok(related)
+}
+
+// Slugs end in the anime's id, like `/one-piece-100`.
+fn key(href: &str) -> &str {
+	let path = href.split('?').next().unwrap_or(href);
+	path.rsplit_once('-').map_or(path, |(_, key)| key)
+}
+
 pub async fn episodes(id: &str) -> Result<Vec<Episode>, anyhow::Error> {
-	let json: Value = reqwest::get(format!("https://hianime.to/ajax/v2/episode/list/{id}"))
-		.await?
-		.json()
-		.await?;
+	let json: Value = mirrors::json(Site::HiAnime, &format!("/ajax/v2/episode/list/{id}")).await?;
 
-	let html = json["html"].as_str().unwrap();
+	let html = json["html"].as_str().context("No episodes html")?;
+	let anime = AnimeId::new(Provider::HiAnime, id);
 	let document = kuchikiki::parse_html().one(html);
 	let episodes = document
 		.select(".ep-item")
 		.map_err(|_| anyhow::anyhow!("Failed to select episodes"))?;
 
-	let episode_list: Vec<Episode> = episodes
+	let episode_list = episodes
 		.map(|episode| {
 			let attributes = episode.attributes.borrow();
 
 			let title = attributes.get("title").unwrap().replace("&#39;", "'");
-			let id = attributes.get("data-id").unwrap().to_string();
-			let number = attributes.get("data-number").unwrap().parse().unwrap();
+			let token = attributes.get("data-id").unwrap();
+			let number = attributes
+				.get("data-number")
+				.context("No episode number")?
+				.parse()?;
+			let id = EpisodeId::with_token(anime.clone(), number, token);
+			let filler = attributes
+				.get("class")
+				.is_some_and(|class| class.contains("ssl-item-filler"));
+			let japanese_title = episode
+				.as_node()
+				.select_first(".ep-name")
+				.ok()
+				.and_then(|name| {
+					let jname = name
+						.attributes
+						.borrow()
+						.get("data-jname")
+						.map(str::to_string);
+					jname
+				});
 
-			Episode { title, id, number }
+			Ok(Episode {
+				japanese_title,
+				filler: Some(filler),
+				..Episode::new(id, title)
+			})
 		})
-		.collect();
+		.collect::<Result<Vec<Episode>, anyhow::Error>>()?;
 
 	Ok(episode_list)
 }
 
-pub async fn servers(ep_id: &str) -> Result<Vec<Server>, anyhow::Error> {
-	let json: Value = reqwest::get(format!(
-		"https://hianime.to/ajax/v2/episode/servers?episodeId={ep_id}"
-	))
-	.await?
-	.json()
+pub async fn servers(id: &EpisodeId, ep_id: &str) -> Result<Vec<Server>, anyhow::Error> {
+	let json: Value = mirrors::json(
+		Site::HiAnime,
+		&format!("/ajax/v2/episode/servers?episodeId={ep_id}"),
+	)
 	.await?;
 
-	let html = json["html"].as_str().unwrap();
-	let document = kuchikiki::parse_html().one(html);
-	let servers = document
-		.select(".server-item")
-		.map_err(|_| anyhow::anyhow!("Failed to select servers"))?;
+	let html = json["html"].as_str().context("No servers html")?;
+	// Parsed in its own scope since kuchikiki nodes can't be held across the requests below.
+	let servers: Vec<(String, String, Locale)> = {
+		let document = kuchikiki::parse_html().one(html);
+		document
+			.select(".server-item")
+			.map_err(|_| anyhow::anyhow!("Failed to select servers"))?
+			.filter_map(|server| {
+				let attributes = server.attributes.borrow();
+				let name = server.text_contents();
+				let server_id = attributes.get("data-id")?.to_string();
+				let locale = match attributes.get("data-type") {
+					Some("sub") => Locale::SoftSub,
+					Some("dub") => Locale::Dub,
+					Some("raw") => Locale::Raw,
+					other => {
+						tracing::debug!(locale = ?other, "skipping server with unknown locale");
+						return None;
+					}
+				};
+
+				Some((name, server_id, locale))
+			})
+			.collect()
+	};
 
 	let mut server_list = Vec::new();
 
-	for server in servers {
-		let attributes = server.attributes.borrow();
-		let name = server.text_contents();
-		let server_id = attributes.get("data-id").unwrap();
-		let locale = match attributes.get("data-type") {
-			Some("sub") => Locale::SoftSub,
-			Some("dub") => Locale::Dub,
-			Some("raw") => Locale::Raw,
-			_ => unimplemented!("Unknown locale"),
-		};
-
-		let json: Value = reqwest::get(format!(
-			"https://hianime.to/ajax/v2/episode/sources?id={server_id}"
-		))
-		.await?
-		.json()
+	for (name, server_id, locale) in servers {
+		let json: Value = mirrors::json(
+			Site::HiAnime,
+			&format!("/ajax/v2/episode/sources?id={server_id}"),
+		)
 		.await?;
 
-		let url = json["link"].as_str().unwrap().to_string();
+		let url = json["link"].as_str().context("No server link")?.to_string();
 
 		let name = format!("{} · {locale}", name.trim());
-		server_list.push(Server { name, locale, url });
+		let id = ServerId::new(id.clone(), &name);
+		server_list.push(Server {
+			name,
+			locale,
+			url,
+			id,
+		});
 	}
 
 	Ok(server_list)
 }
 
 pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
+	if extractors::is_supported(url) {
+		return extractors::get_source(url).await;
+	}
+
+	if cfg!(not(feature = "extractor-megacloud")) {
+		return Err(MissingFeature {
+			feature: "extractor-megacloud",
+			what: "MegaCloud sources",
+		}
+		.into());
+	}
+
 	let xrax = url.rsplit_once('/').unwrap().1.split('?').next().unwrap();
 	let (json, secret) = megacloud::get_sources(xrax.to_string()).await?;
 
@@ -122,7 +286,11 @@ pub async fn get_source(url: &str) -> Result<Source, anyhow::Error> {
 	let mut captions: Vec<Caption> = serde_json::from_value(json["tracks"].clone())?;
 	captions.retain(|track| track.kind != "thumbnails");
 
-	Ok(Source { url, captions })
+	Ok(Source {
+		url,
+		captions,
+		headers: extractors::referer_headers(&format!("{}/", megacloud::origin()))?,
+	})
 }
 
 #[cfg(test)]
@@ -132,7 +300,7 @@ mod tests {
 	#[tokio::test]
 	async fn test_search() {
 		let results = search("One Piece").await.unwrap();
-		assert_eq!(results[1].id, "100");
+		assert_eq!(results[1].id.key, "100");
 		assert!(!results.is_empty(), "Results should not be empty");
 	}
 
@@ -142,15 +310,59 @@ mod tests {
 		assert!(!episode_list.is_empty(), "Episode list should not be empty");
 	}
 
+	#[test]
+	fn test_parse_related() {
+		let html = r#"
+			<div class="os-list">
+				<a href="/jujutsu-kaisen-tv-534" title="Jujutsu Kaisen" class="os-item active">
+					<div class="title">Season 1</div>
+					<div class="season-poster" style="background-image: url(https://img/1.jpg);"></div>
+				</a>
+				<a href="/jujutsu-kaisen-2nd-season-18413" title="Jujutsu Kaisen 2nd Season" class="os-item">
+					<div class="title">Season 2</div>
+				</a>
+			</div>
+			<section class="block_area block_area_sidebar">
+				<div class="cat-heading">Related Anime</div>
+				<ul>
+					<li>
+						<img class="film-poster-img" data-src="https://img/0.jpg">
+						<h3 class="film-name"><a href="/jujutsu-kaisen-0-movie-17763" title="Jujutsu Kaisen 0">JJK 0</a></h3>
+						<div class="tick"><div class="tick-item tick-sub">1</div> Movie</div>
+					</li>
+				</ul>
+			</section>
+		"#;
+
+		let related = parse_related(html, "534").unwrap();
+		assert_eq!(related.len(), 2);
+		assert_eq!(related[0].id.key, "18413");
+		assert_eq!(related[0].relation, Relation::Season);
+		assert_eq!(related[1].title, "Jujutsu Kaisen 0");
+		assert_eq!(related[1].relation, Relation::Movie);
+		assert_eq!(related[1].format.as_deref(), Some("Movie"));
+		assert_eq!(related[1].poster.as_deref(), Some("https://img/0.jpg"));
+	}
+
+	#[tokio::test]
+	async fn test_related() {
+		let related = related("534").await.unwrap();
+		assert!(!related.is_empty(), "Related should not be empty");
+	}
+
+	fn episode() -> EpisodeId {
+		EpisodeId::new(AnimeId::new(Provider::HiAnime, "100"), 1.into())
+	}
+
 	#[tokio::test]
 	async fn test_servers() {
-		let servers = servers("2142").await.unwrap();
+		let servers = servers(&episode(), "2142").await.unwrap();
 		assert!(!servers.is_empty(), "Servers should not be empty");
 	}
 
 	#[tokio::test]
 	async fn test_get_source() {
-		let servers = servers("2142").await.unwrap();
+		let servers = servers(&episode(), "2142").await.unwrap();
 		assert!(!servers.is_empty(), "Can't test source without servers");
 
 		let source = get_source(&servers[0].url).await.unwrap();
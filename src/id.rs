@@ -0,0 +1,209 @@
+use std::{
+	fmt,
+	hash::{Hash, Hasher},
+	str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{EpisodeNumber, Provider};
+
+// An anime on one provider, written as `provider:key` (e.g. `hianime:100`).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AnimeId {
+	pub provider: Provider,
+	pub key: String,
+}
+
+// An episode by anime and number, written as `provider:key:number`, so it outlives the provider's own tokens.
+#[derive(Clone, Debug)]
+pub struct EpisodeId {
+	pub anime: AnimeId,
+	pub number: EpisodeNumber,
+	// Provider token from the listing this id came from, which saves re-resolving it. Not part of the id itself.
+	pub(crate) token: Option<String>,
+}
+
+// A server by episode and name, written as `provider:key:number:name`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ServerId {
+	pub episode: EpisodeId,
+	pub name: String,
+}
+
+impl AnimeId {
+	pub fn new(provider: Provider, key: impl Into<String>) -> Self {
+		AnimeId {
+			provider,
+			key: key.into(),
+		}
+	}
+}
+
+impl EpisodeId {
+	pub fn new(anime: AnimeId, number: EpisodeNumber) -> Self {
+		EpisodeId {
+			anime,
+			number,
+			token: None,
+		}
+	}
+
+	pub(crate) fn with_token(
+		anime: AnimeId, number: EpisodeNumber, token: impl Into<String>,
+	) -> Self {
+		EpisodeId {
+			anime,
+			number,
+			token: Some(token.into()),
+		}
+	}
+
+	pub fn provider(&self) -> Provider {
+		self.anime.provider
+	}
+}
+
+impl ServerId {
+	pub fn new(episode: EpisodeId, name: impl Into<String>) -> Self {
+		ServerId {
+			episode,
+			name: name.into(),
+		}
+	}
+}
+
+impl PartialEq for EpisodeId {
+	fn eq(&self, other: &Self) -> bool {
+		self.anime == other.anime && self.number == other.number
+	}
+}
+
+impl Eq for EpisodeId {}
+
+impl Hash for EpisodeId {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.anime.hash(state);
+		self.number.hash(state);
+	}
+}
+
+impl fmt::Display for AnimeId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let provider = self.provider.to_string().to_lowercase();
+		write!(f, "{provider}:{}", self.key)
+	}
+}
+
+impl fmt::Display for EpisodeId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}", self.anime, self.number)
+	}
+}
+
+impl fmt::Display for ServerId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}", self.episode, self.name)
+	}
+}
+
+impl FromStr for AnimeId {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (provider, key) = s
+			.split_once(':')
+			.ok_or_else(|| anyhow::anyhow!("Expected provider:key, got {s}"))?;
+		let provider = Provider::from(provider)
+			.ok_or_else(|| anyhow::anyhow!("Unknown provider {provider}"))?;
+		anyhow::ensure!(
+			!key.is_empty() && !key.contains(':'),
+			"Invalid anime key {key:?}"
+		);
+
+		Ok(AnimeId::new(provider, key))
+	}
+}
+
+impl FromStr for EpisodeId {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (anime, number) = s
+			.rsplit_once(':')
+			.ok_or_else(|| anyhow::anyhow!("Expected provider:key:number, got {s}"))?;
+		Ok(EpisodeId::new(anime.parse()?, number.parse()?))
+	}
+}
+
+impl FromStr for ServerId {
+	type Err = anyhow::Error;
+
+	// Names may contain anything, so the episode is always the first three fields.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut fields = s.splitn(4, ':');
+		let (Some(provider), Some(key), Some(number), Some(name)) =
+			(fields.next(), fields.next(), fields.next(), fields.next())
+		else {
+			anyhow::bail!("Expected provider:key:number:name, got {s}");
+		};
+
+		let episode = format!("{provider}:{key}:{number}").parse()?;
+		Ok(ServerId::new(episode, name))
+	}
+}
+
+// Every id serializes as its string form so it can be stored and sent back as is.
+macro_rules! string_serde {
+	($($id:ty),*) => {$(
+		impl Serialize for $id {
+			fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				serializer.collect_str(self)
+			}
+		}
+
+		impl<'de> Deserialize<'de> for $id {
+			fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				let s = String::deserialize(deserializer)?;
+				s.parse().map_err(serde::de::Error::custom)
+			}
+		}
+	)*};
+}
+
+string_serde!(AnimeId, EpisodeId, ServerId);
+
+#[cfg(all(test, feature = "hianime", feature = "animepahe"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_round_trip() {
+		let anime: AnimeId = "hianime:100".parse().unwrap();
+		assert_eq!(anime, AnimeId::new(Provider::HiAnime, "100"));
+		assert_eq!(anime.to_string(), "hianime:100");
+
+		let episode =
+			EpisodeId::with_token(AnimeId::new(Provider::AnimePahe, "4"), 12.into(), "abc/def");
+		assert_eq!(episode.to_string(), "animepahe:4:12");
+		assert_eq!("AnimePahe:4:12".parse::<EpisodeId>().unwrap(), episode);
+		assert_eq!(
+			"hianime:100:SP1".parse::<EpisodeId>().unwrap().number,
+			EpisodeNumber::special(1)
+		);
+
+		let server = ServerId::new(episode, "HorribleSubs · 1080p: HardSub");
+		let json = serde_json::to_string(&server).unwrap();
+		assert_eq!(json, r#""animepahe:4:12:HorribleSubs · 1080p: HardSub""#);
+		assert_eq!(serde_json::from_str::<ServerId>(&json).unwrap(), server);
+	}
+
+	#[test]
+	fn test_invalid() {
+		assert!("100".parse::<AnimeId>().is_err());
+		assert!("crunchyroll:100".parse::<AnimeId>().is_err());
+		assert!("hianime:".parse::<AnimeId>().is_err());
+		assert!("hianime:100:one".parse::<EpisodeId>().is_err());
+		assert!("hianime:100:1".parse::<ServerId>().is_err());
+	}
+}
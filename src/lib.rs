@@ -1,27 +1,67 @@
+// Without any provider the dispatch functions below have no arms to use their arguments,
+// and the ids, which hold a `Provider`, can't be constructed.
+#![cfg_attr(
+	not(any(feature = "hianime", feature = "animekai", feature = "animepahe")),
+	allow(dead_code, unreachable_code, unused_variables)
+)]
+
+#[cfg(feature = "animekai")]
 mod animekai;
+#[cfg(feature = "animepahe")]
 mod animepahe;
+#[cfg(feature = "aniskip")]
 pub mod aniskip;
+pub mod antibot;
+pub mod deobfuscate;
+pub mod diagnose;
+pub mod extractors;
+#[cfg(feature = "hianime")]
 mod hianime;
-mod mal;
-
-use serde::{
-	de::{self, MapAccess, Visitor},
-	Deserialize, Deserializer, Serialize,
-};
-use std::fmt;
-
-#[derive(Debug, PartialEq, Serialize)]
+mod id;
+#[cfg(feature = "mal")]
+pub mod mal;
+pub mod mirrors;
+mod number;
+#[cfg(feature = "proxy")]
+pub mod proxy;
+#[cfg(feature = "mal")]
+pub mod seasons;
+pub mod telemetry;
+
+pub use id::{AnimeId, EpisodeId, ServerId};
+pub use number::{EpisodeKind, EpisodeNumber};
+
+use futures::{stream, stream::BoxStream, StreamExt as _, TryStreamExt as _};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
 pub enum Provider {
+	#[cfg(feature = "hianime")]
 	HiAnime,
+	#[cfg(feature = "animekai")]
 	AnimeKai,
+	#[cfg(feature = "animepahe")]
 	AnimePahe,
 }
 
 impl Provider {
+	pub const ALL: &'static [Provider] = &[
+		#[cfg(feature = "hianime")]
+		Provider::HiAnime,
+		#[cfg(feature = "animekai")]
+		Provider::AnimeKai,
+		#[cfg(feature = "animepahe")]
+		Provider::AnimePahe,
+	];
+
 	pub fn from(s: &str) -> Option<Self> {
 		match s.to_lowercase().as_str() {
+			#[cfg(feature = "hianime")]
 			"hianime" => Some(Provider::HiAnime),
+			#[cfg(feature = "animekai")]
 			"animekai" => Some(Provider::AnimeKai),
+			#[cfg(feature = "animepahe")]
 			"animepahe" => Some(Provider::AnimePahe),
 			_ => None,
 		}
@@ -30,27 +70,49 @@ impl Provider {
 
 impl fmt::Display for Provider {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		match self {
+		match *self {
+			#[cfg(feature = "hianime")]
 			Provider::HiAnime => write!(f, "HiAnime"),
+			#[cfg(feature = "animekai")]
 			Provider::AnimeKai => write!(f, "AnimeKai"),
+			#[cfg(feature = "animepahe")]
 			Provider::AnimePahe => write!(f, "AnimePahe"),
 		}
 	}
 }
 
+// Returned, inside `anyhow::Error`, when a source needs a cargo feature this build was compiled without.
+#[derive(Debug, thiserror::Error)]
+#[error("{what} require the `{feature}` feature")]
+pub struct MissingFeature {
+	pub feature: &'static str,
+	pub what: &'static str,
+}
+
+#[tracing::instrument(
+	skip_all,
+	fields(%provider, operation = "search", query = %query),
+	err(level = "debug")
+)]
 pub async fn search(provider: &Provider, query: &str) -> Result<Vec<SearchResult>, anyhow::Error> {
-	match provider {
-		Provider::HiAnime => hianime::search(query).await,
-		Provider::AnimeKai => animekai::search(query).await,
-		Provider::AnimePahe => animepahe::search(query).await,
-	}
+	telemetry::observe(provider, "search", async {
+		match *provider {
+			#[cfg(feature = "hianime")]
+			Provider::HiAnime => hianime::search(query).await,
+			#[cfg(feature = "animekai")]
+			Provider::AnimeKai => animekai::search(query).await,
+			#[cfg(feature = "animepahe")]
+			Provider::AnimePahe => animepahe::search(query).await,
+		}
+	})
+	.await
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct SearchResult {
 	pub title: String,
 	pub poster: String,
-	pub id: String,
+	pub id: AnimeId,
 }
 
 impl fmt::Display for SearchResult {
@@ -59,111 +121,206 @@ impl fmt::Display for SearchResult {
 	}
 }
 
-pub async fn episodes(provider: &Provider, id: &str) -> Result<Vec<Episode>, anyhow::Error> {
-	match provider {
-		Provider::HiAnime => hianime::episodes(id).await,
-		Provider::AnimeKai => animekai::episodes(id).await,
-		Provider::AnimePahe => animepahe::episodes(id).await,
+// Other seasons and related entries listed on an anime's page, excluding the anime itself.
+#[tracing::instrument(
+	skip_all,
+	fields(provider = %id.provider, operation = "related", %id),
+	err(level = "debug")
+)]
+pub async fn related(id: &AnimeId) -> Result<Vec<Related>, anyhow::Error> {
+	telemetry::observe(&id.provider, "related", async {
+		match id.provider {
+			#[cfg(feature = "hianime")]
+			Provider::HiAnime => hianime::related(&id.key).await,
+			#[cfg(feature = "animekai")]
+			Provider::AnimeKai => animekai::related(&id.key).await,
+			// AnimePahe links relations by session, which isn't the id search results use.
+			#[cfg(feature = "animepahe")]
+			Provider::AnimePahe => anyhow::bail!("AnimePahe doesn't support related entries"),
+		}
+	})
+	.await
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Relation {
+	Season,
+	Sequel,
+	Prequel,
+	SideStory,
+	Movie,
+	Other,
+}
+
+impl Relation {
+	// Maps a relation label from a provider page, like `Side Story` or `Movie`.
+	// Formats like `OVA` say nothing about how an entry is related, so they're left as Other.
+	pub fn from_label(label: &str) -> Self {
+		let label = label.trim().to_lowercase().replace(['_', '-'], " ");
+		match label.as_str() {
+			"season" => Relation::Season,
+			"sequel" => Relation::Sequel,
+			"prequel" => Relation::Prequel,
+			"side story" | "spin off" | "alternative" => Relation::SideStory,
+			"movie" => Relation::Movie,
+			_ => Relation::Other,
+		}
+	}
+}
+
+// The format a provider page labels an entry with, like `TV` or `OVA`.
+#[cfg_attr(not(any(feature = "hianime", feature = "animekai")), allow(dead_code))]
+pub(crate) fn format_label(label: &str) -> Option<String> {
+	const FORMATS: &[&str] = &["TV", "Movie", "OVA", "ONA", "Special", "Music"];
+
+	let label = label.trim();
+	FORMATS
+		.iter()
+		.find(|format| format.eq_ignore_ascii_case(label))
+		.map(|format| format.to_string())
+}
+
+impl fmt::Display for Relation {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Relation::Season => write!(f, "Season"),
+			Relation::Sequel => write!(f, "Sequel"),
+			Relation::Prequel => write!(f, "Prequel"),
+			Relation::SideStory => write!(f, "Side story"),
+			Relation::Movie => write!(f, "Movie"),
+			Relation::Other => write!(f, "Related"),
+		}
+	}
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Related {
+	pub title: String,
+	pub poster: Option<String>,
+	pub relation: Relation,
+	pub format: Option<String>,
+	pub id: AnimeId,
+}
+
+impl fmt::Display for Related {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match (self.relation, &self.format) {
+			(Relation::Other, Some(format)) => write!(f, "{} ({format})", self.title),
+			(relation, _) => write!(f, "{} ({relation})", self.title),
+		}
+	}
+}
+
+#[tracing::instrument(
+	skip_all,
+	fields(provider = %id.provider, operation = "episodes", %id),
+	err(level = "debug")
+)]
+pub async fn episodes(id: &AnimeId) -> Result<Vec<Episode>, anyhow::Error> {
+	telemetry::observe(&id.provider, "episodes", provider_episodes(id)).await
+}
+
+// Unobserved, for operations that list episodes as part of their own request.
+async fn provider_episodes(id: &AnimeId) -> Result<Vec<Episode>, anyhow::Error> {
+	match id.provider {
+		#[cfg(feature = "hianime")]
+		Provider::HiAnime => hianime::episodes(&id.key).await,
+		#[cfg(feature = "animekai")]
+		Provider::AnimeKai => animekai::episodes(&id.key).await,
+		#[cfg(feature = "animepahe")]
+		Provider::AnimePahe => animepahe::episodes(&id.key).await,
 	}
 }
 
-impl<'de> Deserialize<'de> for SearchResult {
-	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-	where
-		D: Deserializer<'de>,
-	{
-		struct SearchResultVisitor;
-
-		impl<'de> Visitor<'de> for SearchResultVisitor {
-			type Value = SearchResult;
-
-			fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-				formatter.write_str("a map with title, poster, and id fields")
-			}
-
-			fn visit_map<M>(self, mut map: M) -> Result<SearchResult, M::Error>
-			where
-				M: MapAccess<'de>,
-			{
-				let mut title = None;
-				let mut poster = None;
-				let mut id = None;
-
-				while let Some(key) = map.next_key::<String>()? {
-					match key.as_str() {
-						"title" => title = Some(map.next_value()?),
-						"poster" => poster = Some(map.next_value()?),
-						"id" => match map.next_value::<i32>() {
-							Ok(id_i32) => id = Some(id_i32.to_string()),
-							Err(_) => id = Some(map.next_value()?),
-						},
-						_ => {
-							map.next_value::<serde::de::IgnoredAny>()?;
-						}
-					}
-				}
-
-				let title = title.ok_or_else(|| de::Error::missing_field("title"))?;
-				let poster = poster.ok_or_else(|| de::Error::missing_field("poster"))?;
-				let id = id.ok_or_else(|| de::Error::missing_field("id"))?;
-
-				Ok(SearchResult { title, poster, id })
-			}
+// Episodes as they're fetched, for long series where waiting on the whole listing is slow.
+// Only AnimePahe pages its listing, the others yield theirs once it's loaded.
+#[cfg_attr(not(feature = "animepahe"), allow(unused_variables))]
+pub fn episodes_stream(
+	id: &AnimeId, concurrency: usize,
+) -> BoxStream<'static, Result<Episode, anyhow::Error>> {
+	match id.provider {
+		#[cfg(feature = "animepahe")]
+		Provider::AnimePahe => animepahe::episodes_stream(&id.key, concurrency).boxed(),
+		#[allow(unreachable_patterns)]
+		_ => {
+			let id = id.clone();
+			stream::once(async move { episodes(&id).await })
+				.map_ok(|episodes| stream::iter(episodes.into_iter().map(Ok)))
+				.try_flatten()
+				.boxed()
 		}
+	}
+}
 
-		deserializer.deserialize_map(SearchResultVisitor)
+// Episodes numbered `from..=to`, fetching only the pages that cover them where the provider pages.
+#[tracing::instrument(
+	skip_all,
+	fields(provider = %id.provider, operation = "episodes_range", %id, %from, %to),
+	err(level = "debug")
+)]
+pub async fn episodes_range(
+	id: &AnimeId, from: EpisodeNumber, to: EpisodeNumber,
+) -> Result<Vec<Episode>, anyhow::Error> {
+	telemetry::observe(
+		&id.provider,
+		"episodes_range",
+		provider_episodes_range(id, from, to),
+	)
+	.await
+}
+
+async fn provider_episodes_range(
+	id: &AnimeId, from: EpisodeNumber, to: EpisodeNumber,
+) -> Result<Vec<Episode>, anyhow::Error> {
+	match id.provider {
+		#[cfg(feature = "animepahe")]
+		Provider::AnimePahe => animepahe::episodes_range(&id.key, from, to).await,
+		#[allow(unreachable_patterns)]
+		_ => {
+			let mut episodes = provider_episodes(id).await?;
+			episodes.retain(|episode| (from..=to).contains(&episode.number));
+			Ok(episodes)
+		}
 	}
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Episode {
 	pub title: String,
-	pub number: u32,
-	pub id: String,
-}
-
-impl<'de> Deserialize<'de> for Episode {
-	fn deserialize<D>(deserializer: D) -> Result<Episode, D::Error>
-	where
-		D: Deserializer<'de>,
-	{
-		struct EpisodeVisitor;
-
-		impl<'de> Visitor<'de> for EpisodeVisitor {
-			type Value = Episode;
-
-			fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-				formatter.write_str("a map with title, number, and id fields")
-			}
-
-			fn visit_map<M>(self, mut map: M) -> Result<Episode, M::Error>
-			where
-				M: MapAccess<'de>,
-			{
-				let mut title = None;
-				let mut number = None;
-				let mut id = None;
-
-				while let Some(key) = map.next_key::<String>()? {
-					match key.as_str() {
-						"title" => title = Some(map.next_value()?),
-						"episode" => number = Some(map.next_value()?),
-						"session" => id = Some(map.next_value()?),
-						_ => {
-							map.next_value::<serde::de::IgnoredAny>()?;
-						}
-					}
-				}
-
-				let title = title.ok_or_else(|| de::Error::missing_field("title"))?;
-				let number = number.ok_or_else(|| de::Error::missing_field("number"))?;
-				let id = id.ok_or_else(|| de::Error::missing_field("id"))?;
-
-				Ok(Episode { title, number, id })
-			}
-		}
+	pub number: EpisodeNumber,
+	pub id: EpisodeId,
+	// Metadata below is only filled in where the provider exposes it.
+	#[serde(default)]
+	pub japanese_title: Option<String>,
+	#[serde(default)]
+	pub filler: Option<bool>,
+	#[serde(default)]
+	pub air_date: Option<String>,
+	#[serde(default)]
+	pub thumbnail: Option<String>,
+	// Length in seconds.
+	#[serde(default)]
+	pub duration: Option<u32>,
+	#[serde(default)]
+	pub sub: Option<bool>,
+	#[serde(default)]
+	pub dub: Option<bool>,
+}
 
-		deserializer.deserialize_map(EpisodeVisitor)
+impl Episode {
+	pub fn new(id: EpisodeId, title: impl Into<String>) -> Self {
+		Episode {
+			title: title.into(),
+			number: id.number,
+			id,
+			japanese_title: None,
+			filler: None,
+			air_date: None,
+			thumbnail: None,
+			duration: None,
+			sub: None,
+			dub: None,
+		}
 	}
 }
 
@@ -173,19 +330,46 @@ impl fmt::Display for Episode {
 	}
 }
 
-pub async fn servers(provider: &Provider, ep_id: &str) -> Result<Vec<Server>, anyhow::Error> {
-	match provider {
-		Provider::HiAnime => hianime::servers(ep_id).await,
-		Provider::AnimeKai => animekai::servers(ep_id).await,
-		Provider::AnimePahe => animepahe::servers(ep_id).await,
+#[tracing::instrument(
+	skip_all,
+	fields(provider = %id.provider(), operation = "servers", %id),
+	err(level = "debug")
+)]
+pub async fn servers(id: &EpisodeId) -> Result<Vec<Server>, anyhow::Error> {
+	telemetry::observe(&id.provider(), "servers", async {
+		let token = token(id).await?;
+		match id.provider() {
+			#[cfg(feature = "hianime")]
+			Provider::HiAnime => hianime::servers(id, &token).await,
+			#[cfg(feature = "animekai")]
+			Provider::AnimeKai => animekai::servers(id, &token).await,
+			#[cfg(feature = "animepahe")]
+			Provider::AnimePahe => animepahe::servers(id, &token).await,
+		}
+	})
+	.await
+}
+
+// Ids parsed from a string don't carry the provider's token, so it's looked up again by episode number.
+async fn token(id: &EpisodeId) -> Result<String, anyhow::Error> {
+	if let Some(token) = &id.token {
+		return Ok(token.clone());
 	}
+
+	provider_episodes_range(&id.anime, id.number, id.number)
+		.await?
+		.into_iter()
+		.find(|episode| episode.number == id.number)
+		.and_then(|episode| episode.id.token)
+		.ok_or_else(|| anyhow::anyhow!("No episode {} for {}", id.number, id.anime))
 }
 
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct Server {
 	pub name: String,
 	pub locale: Locale,
 	pub url: String,
+	pub id: ServerId,
 }
 
 impl fmt::Display for Server {
@@ -194,15 +378,41 @@ impl fmt::Display for Server {
 	}
 }
 
+// Resolves a stored server id, whose url may have expired, through a fresh server listing.
+#[tracing::instrument(
+	skip_all,
+	fields(provider = %id.episode.provider(), operation = "source", %id),
+	err(level = "debug")
+)]
+pub async fn source(id: &ServerId) -> Result<Source, anyhow::Error> {
+	let server = servers(&id.episode)
+		.await?
+		.into_iter()
+		.find(|server| server.id == *id)
+		.ok_or_else(|| anyhow::anyhow!("No server {}", id.name))?;
+	get_source(&id.episode.provider(), &server.url).await
+}
+
+#[tracing::instrument(
+	skip_all,
+	fields(%provider, operation = "get_source", url = %telemetry::redact(url)),
+	err(level = "debug")
+)]
 pub async fn get_source(provider: &Provider, url: &str) -> Result<Source, anyhow::Error> {
-	match provider {
-		Provider::HiAnime => hianime::get_source(url).await,
-		Provider::AnimeKai => animekai::get_source(url).await,
-		Provider::AnimePahe => animepahe::get_source(url).await,
-	}
+	telemetry::observe(provider, "get_source", async {
+		match *provider {
+			#[cfg(feature = "hianime")]
+			Provider::HiAnime => hianime::get_source(url).await,
+			#[cfg(feature = "animekai")]
+			Provider::AnimeKai => animekai::get_source(url).await,
+			#[cfg(feature = "animepahe")]
+			Provider::AnimePahe => animepahe::get_source(url).await,
+		}
+	})
+	.await
 }
 
-#[derive(Debug, Default, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Locale {
 	#[default]
 	HardSub,
@@ -222,10 +432,11 @@ impl fmt::Display for Locale {
 	}
 }
 
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct Source {
 	pub url: String,
 	pub captions: Vec<Caption>,
+	pub headers: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
@@ -1,21 +1,25 @@
-use std::{cmp::min, str::Chars};
+use std::{cmp::min, str::Chars, time::Instant};
 
 use anyhow::Context as _;
 use serde_json::Value;
 
+use crate::telemetry;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct SearchResult {
 	pub title: String,
 	pub id: String,
 }
 
+#[tracing::instrument(fields(status = tracing::field::Empty), err(level = "debug"))]
 pub async fn search(query: &str) -> Result<SearchResult, anyhow::Error> {
-	let json: Value = reqwest::get(format!(
-		"https://myanimelist.net/search/prefix.json?type=anime&keyword={query}"
-	))
-	.await?
-	.json()
-	.await?;
+	let url = format!("https://myanimelist.net/search/prefix.json?type=anime&keyword={query}");
+	let started = Instant::now();
+	let response = reqwest::get(&url).await?;
+	let status = response.status();
+	let text = response.text().await?;
+	telemetry::response(&url, status, started.elapsed(), &text);
+	let json: Value = serde_json::from_str(&text)?;
 
 	let items = json["categories"][0]["items"]
 		.as_array()
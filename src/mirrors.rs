@@ -0,0 +1,327 @@
+use std::{collections::HashMap, env, fmt, future::Future, sync::RwLock};
+
+use anyhow::Context as _;
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+
+use crate::{
+	antibot::{self, Blocked, Page},
+	telemetry,
+};
+
+// Sites whose domain protozoa builds urls from, including embeds that move as often as the providers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Site {
+	HiAnime,
+	AnimeKai,
+	AnimePahe,
+	Kwik,
+	MegaCloud,
+}
+
+impl Site {
+	pub const ALL: &'static [Site] = &[
+		Site::HiAnime,
+		Site::AnimeKai,
+		Site::AnimePahe,
+		Site::Kwik,
+		Site::MegaCloud,
+	];
+
+	pub fn from(s: &str) -> Option<Self> {
+		Site::ALL
+			.iter()
+			.copied()
+			.find(|site| site.to_string().eq_ignore_ascii_case(s))
+	}
+
+	fn defaults(&self) -> &'static [&'static str] {
+		match self {
+			Site::HiAnime => &[
+				"https://hianime.to",
+				"https://hianime.nz",
+				"https://hianime.sx",
+			],
+			Site::AnimeKai => &["https://animekai.to", "https://animekai.bz"],
+			Site::AnimePahe => &[
+				"https://animepahe.ru",
+				"https://animepahe.com",
+				"https://animepahe.org",
+			],
+			Site::Kwik => &["https://kwik.si", "https://kwik.cx"],
+			Site::MegaCloud => &["https://megacloud.tv", "https://megacloud.blog"],
+		}
+	}
+}
+
+impl fmt::Display for Site {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Site::HiAnime => write!(f, "HiAnime"),
+			Site::AnimeKai => write!(f, "AnimeKai"),
+			Site::AnimePahe => write!(f, "AnimePahe"),
+			Site::Kwik => write!(f, "Kwik"),
+			Site::MegaCloud => write!(f, "MegaCloud"),
+		}
+	}
+}
+
+lazy_static! {
+	static ref MIRRORS: RwLock<HashMap<Site, Vec<String>>> = RwLock::new(load());
+}
+
+fn load() -> HashMap<Site, Vec<String>> {
+	Site::ALL
+		.iter()
+		.map(|site| {
+			let mirrors = from_env(*site).unwrap_or_else(|| defaults(*site));
+			sync(*site, &mirrors);
+			(*site, mirrors)
+		})
+		.collect()
+}
+
+fn defaults(site: Site) -> Vec<String> {
+	site.defaults()
+		.iter()
+		.map(|mirror| mirror.to_string())
+		.collect()
+}
+
+// e.g. `PROTOZOA_HIANIME_MIRRORS=https://hianime.sx,https://hianime.to`.
+fn from_env(site: Site) -> Option<Vec<String>> {
+	let value = env::var(format!(
+		"PROTOZOA_{}_MIRRORS",
+		site.to_string().to_uppercase()
+	))
+	.ok()?;
+	let mirrors = parse_list(&value);
+	(!mirrors.is_empty()).then_some(mirrors)
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+	value
+		.split(',')
+		.map(|mirror| mirror.trim().trim_end_matches('/').to_string())
+		.filter(|mirror| !mirror.is_empty())
+		.collect()
+}
+
+// The mirrors of `site` in the order they're tried, starting with the last one that worked.
+pub fn get(site: Site) -> Vec<String> {
+	MIRRORS.read().unwrap()[&site].clone()
+}
+
+pub fn base(site: Site) -> String {
+	get(site).swap_remove(0)
+}
+
+// Replaces the mirrors of `site` for every later request, ignoring an empty list.
+pub fn set(site: Site, mirrors: &[String]) {
+	let mirrors: Vec<String> = mirrors
+		.iter()
+		.map(|mirror| mirror.trim_end_matches('/').to_string())
+		.collect();
+	if mirrors.is_empty() {
+		return;
+	}
+
+	sync(site, &mirrors);
+	MIRRORS.write().unwrap().insert(site, mirrors);
+}
+
+// Parses an override like `hianime=https://hianime.sx,https://hianime.to` and applies it.
+pub fn set_from_str(s: &str) -> Result<(), anyhow::Error> {
+	let (site, mirrors) = parse_override(s)?;
+	set(site, &mirrors);
+	Ok(())
+}
+
+fn parse_override(s: &str) -> Result<(Site, Vec<String>), anyhow::Error> {
+	let (site, mirrors) = s
+		.split_once('=')
+		.ok_or_else(|| anyhow::anyhow!("Expected site=url[,url...], got {s}"))?;
+	let site = Site::from(site).ok_or_else(|| anyhow::anyhow!("Unknown site {site}"))?;
+	let mirrors = parse_list(mirrors);
+	anyhow::ensure!(!mirrors.is_empty(), "No mirrors for {site}");
+
+	Ok((site, mirrors))
+}
+
+// The path of `url` if it's on one of `site`'s mirrors, so it can be retried on the others.
+#[cfg_attr(not(feature = "animepahe"), allow(dead_code))]
+pub(crate) fn path(site: Site, url: &str) -> Option<String> {
+	strip_mirror(&get(site), url)
+}
+
+fn strip_mirror(mirrors: &[String], url: &str) -> Option<String> {
+	mirrors.iter().find_map(|mirror| {
+		let path = url.strip_prefix(mirror.as_str())?;
+		(path.is_empty() || path.starts_with('/')).then(|| path.to_string())
+	})
+}
+
+#[cfg_attr(not(any(feature = "hianime", feature = "animekai")), allow(dead_code))]
+pub(crate) async fn text(site: Site, path: &str) -> Result<String, anyhow::Error> {
+	let page = fetch(site, path, |url| async move { antibot::get(&url).await }).await?;
+	Ok(page.body)
+}
+
+#[cfg_attr(not(any(feature = "hianime", feature = "animekai")), allow(dead_code))]
+pub(crate) async fn json<T: DeserializeOwned>(site: Site, path: &str) -> Result<T, anyhow::Error> {
+	let text = text(site, path).await?;
+	serde_json::from_str(&text).with_context(|| format!("Failed to parse {site} {path}"))
+}
+
+// Requests `path` from each mirror in turn until one is up, which is then tried first from now on.
+#[tracing::instrument(
+	skip_all,
+	fields(%site, path = %telemetry::redact(path), retries = tracing::field::Empty)
+)]
+pub(crate) async fn fetch<F, Fut>(site: Site, path: &str, get: F) -> Result<Page, anyhow::Error>
+where
+	F: Fn(String) -> Fut,
+	Fut: Future<Output = Result<Page, anyhow::Error>>,
+{
+	let (mirror, page) = try_mirrors(site, self::get(site), path, get).await?;
+	promote(site, &mirror);
+	Ok(page)
+}
+
+// The mirror that answered and its page, with the mirrors passed in so tests don't touch the shared ones.
+async fn try_mirrors<F, Fut>(
+	site: Site, mirrors: Vec<String>, path: &str, get: F,
+) -> Result<(String, Page), anyhow::Error>
+where
+	F: Fn(String) -> Fut,
+	Fut: Future<Output = Result<Page, anyhow::Error>>,
+{
+	let mut last = None;
+	for (retries, mirror) in mirrors.into_iter().enumerate() {
+		if retries > 0 {
+			tracing::Span::current().record("retries", retries);
+		}
+		match get(format!("{mirror}{path}")).await {
+			Ok(page) if is_down(&page) => {
+				tracing::warn!(%mirror, status = page.status.as_u16(), "mirror is down");
+				last = Some(anyhow::anyhow!("{mirror} is down ({})", page.status));
+			}
+			Ok(page) => return Ok((mirror, page)),
+			Err(error) if is_unreachable(&error) => {
+				tracing::warn!(%mirror, error = %format!("{error:#}"), "mirror is unreachable");
+				last = Some(error);
+			}
+			Err(error) => return Err(error),
+		}
+	}
+
+	let error = last.unwrap_or_else(|| anyhow::anyhow!("No mirrors"));
+	Err(error.context(format!("Every {site} mirror failed")))
+}
+
+fn promote(site: Site, mirror: &str) {
+	let mut mirrors = MIRRORS.write().unwrap();
+	let Some(mirrors) = mirrors.get_mut(&site) else {
+		return;
+	};
+	if let Some(index) = mirrors
+		.iter()
+		.position(|m| m == mirror)
+		.filter(|index| *index > 0)
+	{
+		let mirror = mirrors.remove(index);
+		tracing::info!(%site, %mirror, "switching mirror");
+		mirrors.insert(0, mirror);
+		sync(site, mirrors);
+	}
+}
+
+// Hands the MegaCloud mirrors to protozoa-cryptography, which builds its own urls and fails over between them.
+#[cfg_attr(not(feature = "hianime"), allow(unused_variables))]
+fn sync(site: Site, mirrors: &[String]) {
+	#[cfg(feature = "hianime")]
+	if site == Site::MegaCloud {
+		protozoa_cryptography::sources::megacloud::set_origins(mirrors);
+	}
+}
+
+fn is_unreachable(error: &anyhow::Error) -> bool {
+	error.is::<Blocked>()
+		|| error
+			.downcast_ref::<reqwest::Error>()
+			.is_some_and(|error| error.is_connect() || error.is_timeout())
+}
+
+// Gateway errors, and parked or for-sale pages left behind on abandoned domains.
+fn is_down(page: &Page) -> bool {
+	const PARKED: &[&str] = &[
+		"This domain is for sale",
+		"this domain may be for sale",
+		"domain is parked",
+		"parkingcrew.net",
+		"sedoparking.com",
+		"bodis.com",
+		"dan.com/buy-domain",
+		"afternic.com",
+	];
+
+	matches!(page.status.as_u16(), 502..=504 | 520..=530)
+		|| PARKED.iter().any(|marker| page.body.contains(marker))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use reqwest::StatusCode;
+
+	fn response(status: StatusCode, body: &str) -> Page {
+		Page {
+			status,
+			body: body.to_string(),
+		}
+	}
+
+	#[tokio::test]
+	async fn test_failover() {
+		let mirrors =
+			parse_list("https://parked.example, https://down.example, https://up.example/");
+
+		let (mirror, page) = try_mirrors(Site::Kwik, mirrors.clone(), "/e/1", |url| async move {
+			Ok(match url.as_str() {
+				"https://parked.example/e/1" => {
+					response(StatusCode::OK, "This domain is for sale!")
+				}
+				"https://down.example/e/1" => response(StatusCode::BAD_GATEWAY, ""),
+				_ => response(StatusCode::OK, &url),
+			})
+		})
+		.await
+		.unwrap();
+
+		assert_eq!(page.body, "https://up.example/e/1");
+		assert_eq!(mirror, "https://up.example");
+		assert_eq!(
+			strip_mirror(&mirrors, "https://down.example/e/2").as_deref(),
+			Some("/e/2")
+		);
+		assert_eq!(strip_mirror(&mirrors, "https://down.example.com/e/2"), None);
+
+		let error = try_mirrors(Site::Kwik, mirrors, "/e/1", |_| async {
+			Ok(response(StatusCode::SERVICE_UNAVAILABLE, ""))
+		})
+		.await
+		.unwrap_err();
+		assert!(error.to_string().contains("Every Kwik mirror failed"));
+	}
+
+	#[test]
+	fn test_parse_override() {
+		let (site, mirrors) =
+			parse_override("animekai=https://animekai.bz/, https://animekai.to").unwrap();
+		assert_eq!(site, Site::AnimeKai);
+		assert_eq!(mirrors, ["https://animekai.bz", "https://animekai.to"]);
+		assert!(parse_override("animekai=").is_err());
+		assert!(parse_override("crunchyroll=https://a").is_err());
+		assert!(parse_override("https://a").is_err());
+	}
+}
@@ -0,0 +1,295 @@
+use std::{fmt, str::FromStr};
+
+use serde::{
+	de::{self, Visitor},
+	Deserialize, Deserializer, Serialize, Serializer,
+};
+
+// Fractional episodes are kept in thousandths, so 6.5 is 6500.
+const SCALE: u32 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EpisodeKind {
+	Regular,
+	Special,
+	Ova,
+}
+
+// An episode number as the sites write it: `12`, `6.5`, `SP1` or `OVA2`.
+// Regular episodes sort before specials, which sort before OVAs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EpisodeNumber {
+	kind: EpisodeKind,
+	value: u32,
+}
+
+impl EpisodeNumber {
+	// The largest whole number that fits in thousandths; the constructors saturate at it.
+	pub const MAX: u32 = u32::MAX / SCALE;
+
+	pub fn new(number: u32) -> Self {
+		EpisodeNumber::saturating(EpisodeKind::Regular, number)
+	}
+
+	pub fn special(number: u32) -> Self {
+		EpisodeNumber::saturating(EpisodeKind::Special, number)
+	}
+
+	pub fn ova(number: u32) -> Self {
+		EpisodeNumber::saturating(EpisodeKind::Ova, number)
+	}
+
+	pub fn checked(kind: EpisodeKind, number: u32) -> Option<Self> {
+		Some(EpisodeNumber {
+			kind,
+			value: number.checked_mul(SCALE)?,
+		})
+	}
+
+	fn saturating(kind: EpisodeKind, number: u32) -> Self {
+		EpisodeNumber {
+			kind,
+			value: number.min(EpisodeNumber::MAX) * SCALE,
+		}
+	}
+
+	pub fn kind(&self) -> EpisodeKind {
+		self.kind
+	}
+
+	pub fn whole(&self) -> u32 {
+		self.value / SCALE
+	}
+
+	pub fn is_whole(&self) -> bool {
+		self.value.is_multiple_of(SCALE)
+	}
+
+	pub fn as_f32(&self) -> f32 {
+		self.value as f32 / SCALE as f32
+	}
+
+	// The number MyAnimeList and AniSkip use, which only exists for whole regular episodes.
+	pub fn mal(&self) -> Option<u32> {
+		(self.kind == EpisodeKind::Regular && self.is_whole()).then(|| self.whole())
+	}
+
+	// Shifts a regular episode by whole episodes, for moving between absolute and per-season numbering.
+	pub fn shift(&self, episodes: i64) -> Option<Self> {
+		if self.kind != EpisodeKind::Regular {
+			return None;
+		}
+
+		let value = i64::from(self.value) + episodes * i64::from(SCALE);
+		Some(EpisodeNumber {
+			kind: self.kind,
+			value: u32::try_from(value).ok()?,
+		})
+	}
+
+	fn parse_decimal(s: &str) -> Option<u32> {
+		let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+		if whole.is_empty() || fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+			return None;
+		}
+
+		let fraction = format!("{fraction:0<3}").parse::<u32>().ok()?;
+		whole
+			.parse::<u32>()
+			.ok()?
+			.checked_mul(SCALE)?
+			.checked_add(fraction)
+	}
+}
+
+impl From<u32> for EpisodeNumber {
+	fn from(number: u32) -> Self {
+		EpisodeNumber::new(number)
+	}
+}
+
+impl fmt::Display for EpisodeNumber {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.kind {
+			EpisodeKind::Regular => (),
+			EpisodeKind::Special => write!(f, "SP")?,
+			EpisodeKind::Ova => write!(f, "OVA")?,
+		}
+
+		write!(f, "{}", self.whole())?;
+		if !self.is_whole() {
+			let fraction = format!("{:03}", self.value % SCALE);
+			write!(f, ".{}", fraction.trim_end_matches('0'))?;
+		}
+		Ok(())
+	}
+}
+
+impl FromStr for EpisodeNumber {
+	type Err = anyhow::Error;
+
+	// Also takes `Special 1`, `OVA` and `OAD 2`, since that's how some sites label them.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let trimmed = s.trim();
+		let upper = trimmed.to_ascii_uppercase();
+		let (kind, rest) = [
+			("SPECIAL", EpisodeKind::Special),
+			("SP", EpisodeKind::Special),
+			("OVA", EpisodeKind::Ova),
+			("OAD", EpisodeKind::Ova),
+		]
+		.into_iter()
+		.find_map(|(prefix, kind)| Some((kind, upper.strip_prefix(prefix)?)))
+		.unwrap_or((EpisodeKind::Regular, &upper));
+
+		let rest = match kind {
+			EpisodeKind::Regular => rest,
+			_ => rest.trim_start_matches([' ', '-', '_']),
+		};
+		let value = match (kind, rest) {
+			(EpisodeKind::Special | EpisodeKind::Ova, "") => Some(SCALE),
+			_ => EpisodeNumber::parse_decimal(rest),
+		};
+
+		value
+			.map(|value| EpisodeNumber { kind, value })
+			.ok_or_else(|| anyhow::anyhow!("Invalid episode number {trimmed:?}"))
+	}
+}
+
+// Whole regular episodes are JSON numbers; fractional ones, specials and OVAs keep their string form.
+impl Serialize for EpisodeNumber {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self.mal() {
+			Some(number) => serializer.serialize_u32(number),
+			None => serializer.collect_str(self),
+		}
+	}
+}
+
+// Accepts the string form as well as JSON numbers, which is what AnimePahe and older history files use.
+impl<'de> Deserialize<'de> for EpisodeNumber {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct EpisodeNumberVisitor;
+
+		impl Visitor<'_> for EpisodeNumberVisitor {
+			type Value = EpisodeNumber;
+
+			fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+				formatter.write_str("an episode number like 12, 6.5, SP1 or OVA2")
+			}
+
+			fn visit_u64<E: de::Error>(self, v: u64) -> Result<EpisodeNumber, E> {
+				u32::try_from(v)
+					.ok()
+					.and_then(|number| EpisodeNumber::checked(EpisodeKind::Regular, number))
+					.ok_or_else(|| E::custom(format!("episode number {v} is out of range")))
+			}
+
+			fn visit_i64<E: de::Error>(self, v: i64) -> Result<EpisodeNumber, E> {
+				let v = u64::try_from(v).map_err(E::custom)?;
+				self.visit_u64(v)
+			}
+
+			fn visit_f64<E: de::Error>(self, v: f64) -> Result<EpisodeNumber, E> {
+				v.to_string().parse().map_err(E::custom)
+			}
+
+			fn visit_str<E: de::Error>(self, v: &str) -> Result<EpisodeNumber, E> {
+				v.parse().map_err(E::custom)
+			}
+		}
+
+		deserializer.deserialize_any(EpisodeNumberVisitor)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(
+			"12".parse::<EpisodeNumber>().unwrap(),
+			EpisodeNumber::new(12)
+		);
+		assert_eq!(
+			"SP1".parse::<EpisodeNumber>().unwrap(),
+			EpisodeNumber::special(1)
+		);
+		assert_eq!(
+			"Special 2".parse::<EpisodeNumber>().unwrap(),
+			EpisodeNumber::special(2)
+		);
+		assert_eq!(
+			"ova".parse::<EpisodeNumber>().unwrap(),
+			EpisodeNumber::ova(1)
+		);
+		assert_eq!(
+			"OAD-3".parse::<EpisodeNumber>().unwrap(),
+			EpisodeNumber::ova(3)
+		);
+
+		let half: EpisodeNumber = "6.5".parse().unwrap();
+		assert_eq!(half.whole(), 6);
+		assert_eq!(half.mal(), None);
+		assert_eq!(half.to_string(), "6.5");
+		assert_eq!(half.shift(-6).unwrap().to_string(), "0.5");
+		assert_eq!(half.shift(-7), None);
+		assert_eq!(EpisodeNumber::special(1).shift(1), None);
+		assert_eq!("6.25".parse::<EpisodeNumber>().unwrap().to_string(), "6.25");
+		assert_eq!(
+			"7.0".parse::<EpisodeNumber>().unwrap(),
+			EpisodeNumber::new(7)
+		);
+
+		for invalid in ["", "x", "1.2345", "-1", "SPx", ".5"] {
+			assert!(invalid.parse::<EpisodeNumber>().is_err(), "{invalid}");
+		}
+	}
+
+	#[test]
+	fn test_order() {
+		let mut numbers: Vec<EpisodeNumber> = ["OVA1", "SP2", "7", "6.5", "SP1", "6", "10"]
+			.iter()
+			.map(|n| n.parse().unwrap())
+			.collect();
+		numbers.sort();
+		let sorted: Vec<String> = numbers.iter().map(ToString::to_string).collect();
+		assert_eq!(sorted, ["6", "6.5", "7", "10", "SP1", "SP2", "OVA1"]);
+	}
+
+	#[test]
+	fn test_serde() {
+		let numbers: Vec<EpisodeNumber> = serde_json::from_str(r#"[1, 6.5, "SP1", "12"]"#).unwrap();
+		assert_eq!(
+			numbers,
+			[
+				EpisodeNumber::new(1),
+				"6.5".parse().unwrap(),
+				EpisodeNumber::special(1),
+				EpisodeNumber::new(12)
+			]
+		);
+		assert_eq!(
+			serde_json::to_string(&numbers).unwrap(),
+			r#"[1,"6.5","SP1",12]"#
+		);
+		assert_eq!(EpisodeNumber::new(3).mal(), Some(3));
+		assert_eq!(EpisodeNumber::special(3).mal(), None);
+	}
+
+	#[test]
+	fn test_overflow() {
+		assert_eq!(EpisodeNumber::new(u32::MAX).whole(), EpisodeNumber::MAX);
+		assert_eq!(EpisodeNumber::ova(4_294_968).to_string(), "OVA4294967");
+		assert_eq!(
+			EpisodeNumber::checked(EpisodeKind::Regular, 4_294_968),
+			None
+		);
+		assert!(serde_json::from_str::<EpisodeNumber>("4294968").is_err());
+		assert!(serde_json::from_str::<EpisodeNumber>("-1").is_err());
+		assert!("4294968".parse::<EpisodeNumber>().is_err());
+	}
+}
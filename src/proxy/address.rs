@@ -0,0 +1,113 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use reqwest::{
+	dns::{Addrs, Name, Resolve, Resolving},
+	Url,
+};
+
+// Resolves like the system resolver but drops loopback, private and link-local addresses, so a
+// playlist can't point the proxy at the machine it runs on or its network.
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+	fn resolve(&self, name: Name) -> Resolving {
+		let host = name.as_str().to_string();
+		Box::pin(async move {
+			let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+				.await?
+				.filter(|addr| is_public(addr.ip()))
+				.collect();
+			if addrs.is_empty() {
+				return Err(format!("{host} doesn't resolve to a public address").into());
+			}
+			Ok(Box::new(addrs.into_iter()) as Addrs)
+		})
+	}
+}
+
+// Ip literals skip the resolver, so they're checked on their own, for the first request and every redirect.
+pub fn is_allowed(url: &Url) -> bool {
+	let Some(host) = url.host_str() else {
+		return false;
+	};
+	let host = host.trim_start_matches('[').trim_end_matches(']');
+	host.parse().map_or(true, is_public)
+}
+
+pub fn is_public(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => is_public_v4(ip),
+		IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+			Some(ip) => is_public_v4(ip),
+			None => is_public_v6(ip),
+		},
+	}
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+	let [a, b, c, _] = ip.octets();
+	!(ip.is_unspecified()
+		|| ip.is_loopback()
+		|| ip.is_private()
+		|| ip.is_link_local()
+		|| ip.is_broadcast()
+		|| ip.is_documentation()
+		|| ip.is_multicast()
+		|| a == 0
+		// Carrier grade NAT, 100.64.0.0/10.
+		|| (a == 100 && b & 0xc0 == 64)
+		// IETF protocol assignments, 192.0.0.0/24.
+		|| (a == 192 && b == 0 && c == 0)
+		// Benchmarking, 198.18.0.0/15.
+		|| (a == 198 && b & 0xfe == 18)
+		// Reserved, 240.0.0.0/4.
+		|| a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+	let first = ip.segments()[0];
+	!(ip.is_unspecified()
+		|| ip.is_loopback()
+		|| ip.is_multicast()
+		// Unique local, fc00::/7.
+		|| first & 0xfe00 == 0xfc00
+		// Link local, fe80::/10.
+		|| first & 0xffc0 == 0xfe80
+		// Documentation, 2001:db8::/32.
+		|| (first == 0x2001 && ip.segments()[1] == 0xdb8))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_is_public() {
+		for ip in [
+			"127.0.0.1",
+			"10.1.2.3",
+			"172.16.0.1",
+			"192.168.1.1",
+			"169.254.169.254",
+			"100.64.0.1",
+			"0.0.0.0",
+			"::1",
+			"fd00::1",
+			"fe80::1",
+			"::ffff:127.0.0.1",
+		] {
+			assert!(!is_public(ip.parse().unwrap()), "{ip}");
+		}
+		for ip in ["1.1.1.1", "104.16.0.1", "2606:4700::1111"] {
+			assert!(is_public(ip.parse().unwrap()), "{ip}");
+		}
+
+		assert!(!is_allowed(
+			&Url::parse("http://169.254.169.254/latest/meta-data").unwrap()
+		));
+		assert!(!is_allowed(&Url::parse("http://[::1]:8080/").unwrap()));
+		assert!(is_allowed(
+			&Url::parse("https://cdn.example.com/master.m3u8").unwrap()
+		));
+	}
+}
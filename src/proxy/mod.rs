@@ -0,0 +1,364 @@
+mod address;
+mod playlist;
+
+use std::{
+	collections::{hash_map::RandomState, BTreeMap, HashMap, HashSet},
+	hash::{BuildHasher as _, Hasher as _},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, RwLock,
+	},
+	time::{Duration, Instant},
+};
+
+use axum::{
+	body::Body,
+	extract::{Path, Query, State},
+	http::{header, HeaderMap, HeaderValue, StatusCode},
+	response::{IntoResponse, Response},
+	routing::get,
+	Router,
+};
+pub use playlist::rewrite;
+use reqwest::{redirect, Client, Url};
+use serde::Deserialize;
+
+use crate::{telemetry, Caption, Source};
+
+// Long enough for a movie with pauses; every `register` sweeps sessions older than this.
+const SESSION_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+// Upstream response headers worth passing on to the player.
+const FORWARDED: [header::HeaderName; 5] = [
+	header::CONTENT_TYPE,
+	header::CONTENT_LENGTH,
+	header::CONTENT_RANGE,
+	header::ACCEPT_RANGES,
+	header::CACHE_CONTROL,
+];
+
+// Redirects a CDN may chain before the segment; the default policy's limit.
+const MAX_REDIRECTS: usize = 10;
+
+struct Session {
+	headers: BTreeMap<String, String>,
+	// Upstream hosts this session may fetch from: the source's, its captions' and any its playlists point at.
+	hosts: HashSet<String>,
+	created: Instant,
+}
+
+// Re-serves registered sources from `base`, which is where `router()` is mounted.
+#[derive(Clone)]
+pub struct Proxy {
+	base: Url,
+	client: Client,
+	sessions: Arc<RwLock<HashMap<String, Session>>>,
+	counter: Arc<AtomicU64>,
+	state: RandomState,
+}
+
+#[derive(Deserialize)]
+struct Target {
+	url: String,
+}
+
+impl Proxy {
+	pub fn new(base: &str) -> Result<Self, anyhow::Error> {
+		let mut base = Url::parse(base)?;
+		if !base.path().ends_with('/') {
+			base.set_path(&format!("{}/", base.path()));
+		}
+
+		let client = Client::builder()
+			.dns_resolver(Arc::new(address::PublicResolver))
+			.redirect(redirect::Policy::custom(|attempt| {
+				if attempt.previous().len() >= MAX_REDIRECTS {
+					attempt.error("Too many redirects")
+				} else if !address::is_allowed(attempt.url()) {
+					attempt.error("Redirected to a private address")
+				} else {
+					attempt.follow()
+				}
+			}))
+			.build()?;
+
+		Ok(Proxy {
+			base,
+			client,
+			sessions: Arc::default(),
+			counter: Arc::default(),
+			state: RandomState::new(),
+		})
+	}
+
+	// Returns a copy of `source` whose stream and captions go through the proxy, with the upstream headers kept server side.
+	pub fn register(&self, source: &Source) -> Result<Source, anyhow::Error> {
+		let id = self.session_id();
+		let hosts = std::iter::once(&source.url)
+			.chain(source.captions.iter().map(|caption| &caption.url))
+			.map(|url| host(&Url::parse(url)?))
+			.collect::<Result<_, anyhow::Error>>()?;
+		{
+			let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+			sessions.retain(|_, session| session.created.elapsed() < SESSION_TTL);
+			sessions.insert(
+				id.clone(),
+				Session {
+					headers: source.headers.clone(),
+					hosts,
+					created: Instant::now(),
+				},
+			);
+		}
+
+		let captions = source
+			.captions
+			.iter()
+			.map(|caption| {
+				Ok(Caption {
+					url: self.url(&id, &Url::parse(&caption.url)?),
+					label: caption.label.clone(),
+					kind: caption.kind.clone(),
+				})
+			})
+			.collect::<Result<_, anyhow::Error>>()?;
+
+		Ok(Source {
+			url: self.url(&id, &Url::parse(&source.url)?),
+			captions,
+			headers: BTreeMap::new(),
+		})
+	}
+
+	pub fn router<S>(&self) -> Router<S> {
+		Router::new()
+			.route("/{id}", get(forward).options(preflight))
+			.with_state(self.clone())
+	}
+
+	fn url(&self, id: &str, upstream: &Url) -> String {
+		let mut url = self.base.join(id).expect("session ids are url safe");
+		url.query_pairs_mut().append_pair("url", upstream.as_str());
+		url.to_string()
+	}
+
+	fn session_id(&self) -> String {
+		let mut hasher = self.state.build_hasher();
+		hasher.write_u64(self.counter.fetch_add(1, Ordering::Relaxed));
+		format!("{:016x}", hasher.finish())
+	}
+
+	fn headers(&self, id: &str) -> Option<BTreeMap<String, String>> {
+		let sessions = self.sessions.read().unwrap_or_else(|e| e.into_inner());
+		sessions.get(id).map(|session| session.headers.clone())
+	}
+
+	fn allows(&self, id: &str, host: &str) -> bool {
+		let sessions = self.sessions.read().unwrap_or_else(|e| e.into_inner());
+		sessions
+			.get(id)
+			.is_some_and(|session| session.hosts.contains(host))
+	}
+
+	fn allow(&self, id: &str, hosts: HashSet<String>) {
+		let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+		if let Some(session) = sessions.get_mut(id) {
+			session.hosts.extend(hosts);
+		}
+	}
+}
+
+fn host(url: &Url) -> Result<String, anyhow::Error> {
+	url.host_str()
+		.map(str::to_string)
+		.ok_or_else(|| anyhow::anyhow!("{url} has no host"))
+}
+
+async fn forward(
+	State(proxy): State<Proxy>, Path(id): Path<String>, Query(target): Query<Target>,
+	request_headers: HeaderMap,
+) -> Response {
+	match fetch(&proxy, &id, &target.url, &request_headers).await {
+		Ok(response) => response,
+		Err((status, message)) => cors((status, message).into_response()),
+	}
+}
+
+#[tracing::instrument(
+	skip_all,
+	fields(url = %telemetry::redact(target), status = tracing::field::Empty)
+)]
+async fn fetch(
+	proxy: &Proxy, id: &str, target: &str, request_headers: &HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+	let headers = proxy.headers(id).ok_or((
+		StatusCode::NOT_FOUND,
+		"Unknown or expired session".to_string(),
+	))?;
+	let url = Url::parse(target).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+	if !matches!(url.scheme(), "http" | "https") {
+		return Err((
+			StatusCode::BAD_REQUEST,
+			"Only http(s) urls can be proxied".to_string(),
+		));
+	}
+	if !url.host_str().is_some_and(|host| proxy.allows(id, host)) {
+		return Err((
+			StatusCode::FORBIDDEN,
+			"Host isn't part of this session".to_string(),
+		));
+	}
+	if !address::is_allowed(&url) {
+		return Err((
+			StatusCode::FORBIDDEN,
+			"Private addresses can't be proxied".to_string(),
+		));
+	}
+
+	let mut request = proxy.client.get(url);
+	for (name, value) in &headers {
+		request = request.header(name, value);
+	}
+	if let Some(range) = request_headers.get(header::RANGE) {
+		request = request.header(header::RANGE, range);
+	}
+
+	let started = Instant::now();
+	let upstream = request.send().await.map_err(|e| {
+		tracing::warn!(error = %e, "upstream request failed");
+		(StatusCode::BAD_GATEWAY, e.to_string())
+	})?;
+
+	let status = upstream.status();
+	let content_type = upstream
+		.headers()
+		.get(header::CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.map(str::to_string);
+
+	if status.is_success() && playlist::is_playlist(upstream.url(), content_type.as_deref()) {
+		let base = upstream.url().clone();
+		let text = upstream
+			.text()
+			.await
+			.map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+		telemetry::response(base.as_str(), status, started.elapsed(), &text);
+		let mut hosts = HashSet::new();
+		let body = rewrite(&text, &base, |url| {
+			if let Some(host) = url.host_str() {
+				hosts.insert(host.to_string());
+			}
+			proxy.url(id, url)
+		});
+		proxy.allow(id, hosts);
+
+		let response = (
+			[(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")],
+			body,
+		);
+		return Ok(cors(response.into_response()));
+	}
+
+	// Segments are streamed through without being read, so there's no body to log.
+	telemetry::response(upstream.url().as_str(), status, started.elapsed(), "");
+	let mut response = Response::builder().status(status);
+	for name in FORWARDED {
+		if let Some(value) = upstream.headers().get(&name) {
+			response = response.header(name, value);
+		}
+	}
+
+	let response = response
+		.body(Body::from_stream(upstream.bytes_stream()))
+		.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+	Ok(cors(response))
+}
+
+async fn preflight() -> Response {
+	cors(StatusCode::NO_CONTENT.into_response())
+}
+
+fn cors(mut response: Response) -> Response {
+	let headers = response.headers_mut();
+	headers.insert(
+		header::ACCESS_CONTROL_ALLOW_ORIGIN,
+		HeaderValue::from_static("*"),
+	);
+	headers.insert(
+		header::ACCESS_CONTROL_ALLOW_METHODS,
+		HeaderValue::from_static("GET, OPTIONS"),
+	);
+	headers.insert(
+		header::ACCESS_CONTROL_ALLOW_HEADERS,
+		HeaderValue::from_static("*"),
+	);
+	headers.insert(
+		header::ACCESS_CONTROL_EXPOSE_HEADERS,
+		HeaderValue::from_static("Content-Length, Content-Range, Accept-Ranges"),
+	);
+	response
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_register() {
+		let proxy = Proxy::new("http://127.0.0.1:3000/proxy").unwrap();
+		let source = Source {
+			url: "https://cdn.example.com/hls/master.m3u8?t=1&s=2".to_string(),
+			captions: vec![Caption {
+				url: "https://cdn.example.com/eng.vtt".to_string(),
+				label: Some("English".to_string()),
+				kind: "captions".to_string(),
+			}],
+			headers: BTreeMap::from([("referer".to_string(), "https://megacloud.tv/".to_string())]),
+		};
+
+		let proxied = proxy.register(&source).unwrap();
+		let url = Url::parse(&proxied.url).unwrap();
+		let id = url.path().strip_prefix("/proxy/").unwrap();
+		assert_eq!(
+			url.query_pairs().find(|(key, _)| key == "url").unwrap().1,
+			source.url
+		);
+		assert!(proxied.captions[0]
+			.url
+			.starts_with(&format!("http://127.0.0.1:3000/proxy/{id}?url=")));
+		assert!(proxied.headers.is_empty());
+		assert_eq!(proxy.headers(id), Some(source.headers));
+		assert_eq!(proxy.headers("missing"), None);
+		assert!(proxy.allows(id, "cdn.example.com"));
+		assert!(!proxy.allows(id, "169.254.169.254"));
+	}
+
+	#[tokio::test]
+	async fn test_fetch_rejects() {
+		let proxy = Proxy::new("http://127.0.0.1:3000/proxy").unwrap();
+		let source = Source {
+			url: "http://127.0.0.1:8080/master.m3u8".to_string(),
+			captions: Vec::new(),
+			headers: BTreeMap::new(),
+		};
+		let proxied = proxy.register(&source).unwrap();
+		let url = Url::parse(&proxied.url).unwrap();
+		let id = url.path().strip_prefix("/proxy/").unwrap();
+
+		let status = |result: Result<Response, (StatusCode, String)>| result.unwrap_err().0;
+		let headers = HeaderMap::new();
+		let metadata = "http://169.254.169.254/latest/meta-data";
+		assert_eq!(
+			status(fetch(&proxy, id, metadata, &headers).await),
+			StatusCode::FORBIDDEN
+		);
+		assert_eq!(
+			status(fetch(&proxy, id, &source.url, &headers).await),
+			StatusCode::FORBIDDEN
+		);
+		assert_eq!(
+			status(fetch(&proxy, "missing", &source.url, &headers).await),
+			StatusCode::NOT_FOUND
+		);
+	}
+}
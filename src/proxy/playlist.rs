@@ -0,0 +1,74 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use reqwest::Url;
+
+pub fn is_playlist(url: &Url, content_type: Option<&str>) -> bool {
+	content_type.is_some_and(|kind| kind.to_ascii_lowercase().contains("mpegurl"))
+		|| url.path().ends_with(".m3u8")
+}
+
+// Points every URI in an HLS playlist (segments, variants, keys, maps and renditions) through `map`.
+pub fn rewrite(playlist: &str, base: &Url, mut map: impl FnMut(&Url) -> String) -> String {
+	lazy_static! {
+		static ref URI: Regex = Regex::new(r#"URI="([^"]*)""#).unwrap();
+	}
+
+	let mut output = String::with_capacity(playlist.len() * 2);
+	for line in playlist.lines() {
+		let trimmed = line.trim();
+		if trimmed.is_empty() {
+			output.push_str(line);
+		} else if trimmed.starts_with('#') {
+			let rewritten =
+				URI.replace_all(line, |captures: &Captures| match base.join(&captures[1]) {
+					Ok(url) => format!(r#"URI="{}""#, map(&url)),
+					Err(_) => captures[0].to_string(),
+				});
+			output.push_str(&rewritten);
+		} else {
+			match base.join(trimmed) {
+				Ok(url) => output.push_str(&map(&url)),
+				Err(_) => output.push_str(line),
+			}
+		}
+		output.push('\n');
+	}
+
+	output
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_rewrite_master() {
+		let master = "#EXTM3U\n#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Japanese\",URI=\"audio/jpn.m3u8\"\n#EXT-X-STREAM-INF:BANDWIDTH=2800000,RESOLUTION=1920x1080,AUDIO=\"aud\"\nindex-1080.m3u8\n#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=90000,URI=\"https://cdn2.example.com/iframes.m3u8\"\n";
+		let base = Url::parse("https://cdn.example.com/hls/abc/master.m3u8?t=1").unwrap();
+		let rewritten = rewrite(master, &base, |url| format!("/proxy?url={url}"));
+		assert_eq!(
+			rewritten,
+			"#EXTM3U\n#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Japanese\",URI=\"/proxy?url=https://cdn.example.com/hls/abc/audio/jpn.m3u8\"\n#EXT-X-STREAM-INF:BANDWIDTH=2800000,RESOLUTION=1920x1080,AUDIO=\"aud\"\n/proxy?url=https://cdn.example.com/hls/abc/index-1080.m3u8\n#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=90000,URI=\"/proxy?url=https://cdn2.example.com/iframes.m3u8\"\n"
+		);
+	}
+
+	#[test]
+	fn test_rewrite_media() {
+		let media = "#EXTM3U\r\n#EXT-X-TARGETDURATION:10\r\n#EXT-X-KEY:METHOD=AES-128,URI=\"/keys/1.key\",IV=0x0123\r\n#EXT-X-MAP:URI=\"init.mp4\"\r\n#EXTINF:10.0,\r\nseg-0.ts?s=abc\r\n\r\n#EXTINF:10.0,\r\n//edge.example.net/seg-1.jpg\r\n#EXT-X-ENDLIST\r\n";
+		let base = Url::parse("https://cdn.example.com/hls/abc/index.m3u8").unwrap();
+		let rewritten = rewrite(media, &base, |url| format!("P[{url}]"));
+		assert_eq!(
+			rewritten,
+			"#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXT-X-KEY:METHOD=AES-128,URI=\"P[https://cdn.example.com/keys/1.key]\",IV=0x0123\n#EXT-X-MAP:URI=\"P[https://cdn.example.com/hls/abc/init.mp4]\"\n#EXTINF:10.0,\nP[https://cdn.example.com/hls/abc/seg-0.ts?s=abc]\n\n#EXTINF:10.0,\nP[https://edge.example.net/seg-1.jpg]\n#EXT-X-ENDLIST\n"
+		);
+	}
+
+	#[test]
+	fn test_is_playlist() {
+		let url = Url::parse("https://cdn.example.com/a/master.m3u8?t=1").unwrap();
+		assert!(is_playlist(&url, None));
+		let url = Url::parse("https://cdn.example.com/a/playlist").unwrap();
+		assert!(is_playlist(&url, Some("application/vnd.apple.mpegURL")));
+		assert!(!is_playlist(&url, Some("video/mp2t")));
+	}
+}
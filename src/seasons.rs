@@ -0,0 +1,308 @@
+use std::{
+	collections::HashMap,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use lazy_static::lazy_static;
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{mal, telemetry, EpisodeId, EpisodeNumber};
+
+// Caps the relation walk, since some franchises chain dozens of entries.
+const MAX_ENTRIES: usize = 30;
+
+lazy_static! {
+	// Franchises walked so far by the MAL id they were looked up with, since every episode of a show resolves the same one.
+	static ref CACHE: Mutex<HashMap<String, Seasons>> = Mutex::default();
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Season {
+	pub mal_id: String,
+	pub title: String,
+	// None while the season is airing.
+	pub episodes: Option<u32>,
+}
+
+// The TV entries of a franchise in airing order, found by following MAL's prequel and sequel relations.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Seasons {
+	pub seasons: Vec<Season>,
+	// The sequel the walk stopped at, if it stopped before the end of the franchise.
+	#[serde(skip)]
+	next: Option<u64>,
+	#[serde(skip)]
+	walked: usize,
+}
+
+struct Entry {
+	season: Season,
+	tv: bool,
+	prequel: Option<u64>,
+	sequel: Option<u64>,
+}
+
+impl Seasons {
+	#[tracing::instrument(err(level = "debug"))]
+	pub async fn fetch(mal_id: &str) -> Result<Self, anyhow::Error> {
+		let mut seasons = Seasons::prequels(mal_id).await?;
+		while seasons.sequel().await? {}
+		Ok(seasons)
+	}
+
+	// Walks back to the first season, leaving the sequels for `extend`.
+	async fn prequels(mal_id: &str) -> Result<Self, anyhow::Error> {
+		let id = mal_id.parse().context("Invalid MAL id")?;
+
+		let mut chain = vec![entry(id).await?];
+		while let Some(prequel) = chain.last().and_then(|entry| entry.prequel) {
+			if chain.len() >= MAX_ENTRIES {
+				break;
+			}
+			chain.push(entry(prequel).await?);
+		}
+
+		let next = chain[0].sequel;
+		let walked = chain.len();
+		let seasons = chain
+			.into_iter()
+			.rev()
+			.filter(|entry| entry.tv)
+			.map(|entry| entry.season)
+			.collect();
+		Ok(Seasons {
+			seasons,
+			next,
+			walked,
+		})
+	}
+
+	// Walks sequels until `absolute` falls within a known season or the franchise ends.
+	async fn extend(&mut self, absolute: EpisodeNumber) -> Result<(), anyhow::Error> {
+		while !self.covers(absolute) && self.sequel().await? {}
+		Ok(())
+	}
+
+	// Adds the next sequel, returning whether there was one to add.
+	async fn sequel(&mut self) -> Result<bool, anyhow::Error> {
+		let Some(id) = self.next.filter(|_| self.walked < MAX_ENTRIES) else {
+			return Ok(false);
+		};
+
+		let entry = entry(id).await?;
+		self.next = entry.sequel;
+		self.walked += 1;
+		if entry.tv {
+			self.seasons.push(entry.season);
+		}
+		Ok(true)
+	}
+
+	// A season that's still airing takes every number past the earlier ones.
+	fn covers(&self, absolute: EpisodeNumber) -> bool {
+		self.seasons
+			.iter()
+			.try_fold(0, |total, season| Some(total + season.episodes?))
+			.is_none_or(|total| absolute.whole() <= total)
+	}
+
+	// Absolute number of the season's first episode, known once every earlier season has finished.
+	pub fn start(&self, mal_id: &str) -> Option<u32> {
+		let mut start = 1;
+		for season in &self.seasons {
+			if season.mal_id == mal_id {
+				return Some(start);
+			}
+			start += season.episodes?;
+		}
+		None
+	}
+
+	pub fn to_absolute(&self, mal_id: &str, number: EpisodeNumber) -> Option<EpisodeNumber> {
+		let start = self.start(mal_id)?;
+		number.shift(i64::from(start) - 1)
+	}
+
+	// Recaps like 6.5 stay in the season of episode 6.
+	pub fn to_season(&self, absolute: EpisodeNumber) -> Option<(&Season, EpisodeNumber)> {
+		let mut offset = 0;
+		for season in &self.seasons {
+			match season.episodes {
+				Some(episodes) if absolute.whole() > offset + episodes => offset += episodes,
+				_ => return Some((season, absolute.shift(-i64::from(offset))?)),
+			}
+		}
+		None
+	}
+}
+
+// Finds the MAL entry and per-entry number of a provider episode, which AnimePahe may number across seasons.
+#[tracing::instrument(skip_all, fields(title = %title, %episode), err(level = "debug"))]
+pub async fn resolve(
+	title: &str, episode: &EpisodeId,
+) -> Result<(String, EpisodeNumber), anyhow::Error> {
+	let continuous = continuous(episode).await?;
+	resolve_number(title, episode.number, continuous).await
+}
+
+// Like `resolve` for a bare number, which runs on into the sequels once it's past the end of the season.
+#[tracing::instrument(err(level = "debug"))]
+pub async fn resolve_number(
+	title: &str, number: EpisodeNumber, continuous: bool,
+) -> Result<(String, EpisodeNumber), anyhow::Error> {
+	let mal_id = mal::search(title).await?.id;
+	let cached = CACHE.lock().unwrap().get(&mal_id).cloned();
+	let mut seasons = match cached {
+		Some(seasons) => seasons,
+		None => Seasons::prequels(&mal_id).await?,
+	};
+
+	let absolute = match continuous {
+		true => Some(number),
+		false => seasons.to_absolute(&mal_id, number),
+	};
+	if let Some(absolute) = absolute {
+		seasons.extend(absolute).await?;
+	}
+	CACHE
+		.lock()
+		.unwrap()
+		.insert(mal_id.clone(), seasons.clone());
+
+	// Entries outside the TV chain, like movies, keep their own numbering.
+	match absolute.and_then(|absolute| seasons.to_season(absolute)) {
+		Some((season, number)) => Ok((season.mal_id.clone(), number)),
+		None => Ok((mal_id, number)),
+	}
+}
+
+// Whether the provider counts this anime's episodes from the start of the franchise.
+async fn continuous(episode: &EpisodeId) -> Result<bool, anyhow::Error> {
+	match episode.provider() {
+		#[cfg(feature = "animepahe")]
+		crate::Provider::AnimePahe => {
+			let first = crate::animepahe::first_episode(&episode.anime.key).await?;
+			Ok(first.whole() > 1)
+		}
+		#[allow(unreachable_patterns)]
+		_ => Ok(false),
+	}
+}
+
+async fn entry(id: u64) -> Result<Entry, anyhow::Error> {
+	let json = jikan(&format!("anime/{id}/full")).await?;
+	let data = &json["data"];
+
+	let relation = |kind: &str| {
+		data["relations"]
+			.as_array()?
+			.iter()
+			.filter(|relation| relation["relation"] == kind)
+			.flat_map(|relation| relation["entry"].as_array().into_iter().flatten())
+			.find(|entry| entry["type"] == "anime")
+			.and_then(|entry| entry["mal_id"].as_u64())
+	};
+
+	Ok(Entry {
+		season: Season {
+			mal_id: id.to_string(),
+			title: data["title"].as_str().unwrap_or_default().to_string(),
+			episodes: data["episodes"]
+				.as_u64()
+				.and_then(|episodes| episodes.try_into().ok()),
+		},
+		tv: matches!(data["type"].as_str(), Some("TV" | "ONA")),
+		prequel: relation("Prequel"),
+		sequel: relation("Sequel"),
+	})
+}
+
+// Jikan allows a few requests a second, so rate limited requests are retried.
+#[tracing::instrument(fields(status = tracing::field::Empty, retries = tracing::field::Empty))]
+async fn jikan(path: &str) -> Result<Value, anyhow::Error> {
+	let url = format!("https://api.jikan.moe/v4/{path}");
+	let mut retries = 3;
+	loop {
+		let started = Instant::now();
+		let response = reqwest::get(&url).await?;
+		let status = response.status();
+		if status == StatusCode::TOO_MANY_REQUESTS && retries > 0 {
+			retries -= 1;
+			tracing::Span::current().record("retries", 3 - retries);
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			continue;
+		}
+
+		let text = response.text().await?;
+		telemetry::response(&url, status, started.elapsed(), &text);
+		anyhow::ensure!(status.is_success(), "{url} returned {status}");
+		return Ok(serde_json::from_str(&text)?);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn attack_on_titan() -> Seasons {
+		let season = |mal_id: &str, episodes| Season {
+			mal_id: mal_id.to_string(),
+			title: String::new(),
+			episodes,
+		};
+		Seasons {
+			seasons: vec![
+				season("16498", Some(25)),
+				season("25777", Some(12)),
+				season("35760", Some(12)),
+				season("38524", Some(10)),
+				season("40028", None),
+			],
+			next: None,
+			walked: 5,
+		}
+	}
+
+	#[test]
+	fn test_to_season() {
+		let seasons = attack_on_titan();
+		let to_season = |absolute: &str| {
+			let (season, number) = seasons.to_season(absolute.parse().unwrap()).unwrap();
+			(season.mal_id.as_str(), number.to_string())
+		};
+
+		assert_eq!(to_season("1"), ("16498", "1".to_string()));
+		assert_eq!(to_season("25"), ("16498", "25".to_string()));
+		assert_eq!(to_season("26"), ("25777", "1".to_string()));
+		assert_eq!(to_season("37.5"), ("25777", "12.5".to_string()));
+		assert_eq!(to_season("50"), ("38524", "1".to_string()));
+		assert_eq!(to_season("75"), ("40028", "16".to_string()));
+		assert!(seasons.to_season(EpisodeNumber::special(1)).is_none());
+	}
+
+	#[test]
+	fn test_to_absolute() {
+		let seasons = attack_on_titan();
+		assert_eq!(seasons.start("35760"), Some(38));
+		assert_eq!(
+			seasons.to_absolute("38524", EpisodeNumber::new(1)),
+			Some(EpisodeNumber::new(50))
+		);
+		assert_eq!(seasons.to_absolute("1", EpisodeNumber::new(1)), None);
+	}
+
+	#[test]
+	fn test_covers() {
+		let mut seasons = attack_on_titan();
+		assert!(seasons.covers(EpisodeNumber::new(500)));
+
+		seasons.seasons.truncate(2);
+		assert!(seasons.covers(EpisodeNumber::new(37)));
+		assert!(seasons.covers("37.5".parse().unwrap()));
+		assert!(!seasons.covers(EpisodeNumber::new(38)));
+	}
+}